@@ -0,0 +1,18 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" tiledversion="1.10.2" name="tiles" tilewidth="32" tileheight="32" tilecount="3" columns="3">
+ <tile id="0">
+  <properties>
+   <property name="glyph" value="#"/>
+  </properties>
+ </tile>
+ <tile id="1">
+  <properties>
+   <property name="tile_type" value="Ground"/>
+  </properties>
+ </tile>
+ <tile id="2">
+  <properties>
+   <property name="glyph" value="="/>
+  </properties>
+ </tile>
+</tileset>
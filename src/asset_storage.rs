@@ -1,12 +1,14 @@
 use ron;
-use std::{fs::{File, DirEntry}, io::Read};
+use std::{fs::{File, DirEntry}, io::Read, path::Path};
 
-use ggez::{audio, Context};
+use ggez::{audio, Context, GameError};
 use ggez::graphics::{Image, Font, Text};
 use std::collections::HashMap;
 
 use crate::SkirmResult;
 
+pub const ANIMATIONS_FILE: &str = "./resources/animations.ron";
+
 pub struct AssetStorage {
     pub images: HashMap<String, Image>,
     pub sounds: HashMap<String, audio::Source>,
@@ -73,16 +75,7 @@ impl AssetStorage {
     }
 
     pub fn load_animations(&mut self) -> SkirmResult {
-        // Animations - open the file, read it into a buffer, deserialize with serde
-        let mut anim_file = File::open("./resources/animations.ron")?;
-        let mut buffer = String::new();
-        anim_file.read_to_string(&mut buffer)?;
-        let animations: HashMap<String, Vec<String>> = match ron::de::from_str(buffer.as_str()) {
-            Ok(result) => result,
-            Err(e) => panic!("Error reading animations.ron, format is corrupt. {:?}", e),
-        };
-
-        self.animations = animations;
+        self.animations = read_animations(ANIMATIONS_FILE)?;
         Ok(())
     }
 
@@ -91,3 +84,17 @@ impl AssetStorage {
         sound.play().unwrap();
     }
 }
+
+pub fn read_animations<P: AsRef<Path>>(path: P) -> SkirmResult<HashMap<String, Vec<String>>> {
+    // Animations - open the file, read it into a buffer, deserialize with serde
+    let mut anim_file = File::open(path)?;
+    let mut buffer = String::new();
+    anim_file.read_to_string(&mut buffer)?;
+    match ron::de::from_str(buffer.as_str()) {
+        Ok(result) => Ok(result),
+        Err(e) => {
+            let msg = format!("Error reading animations.ron, format is corrupt. {:?}", e);
+            Err(GameError::ResourceLoadError(msg))
+        },
+    }
+}
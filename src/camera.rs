@@ -1,3 +1,5 @@
+use ggez::graphics::Rect;
+
 use crate::{Point2, Vector2};
 
 const CAMERA_SLOP: f32 = 10.0;
@@ -8,6 +10,7 @@ pub struct Camera {
     pub screen_size: Vector2,
     pub speed: f32,
     pub focus: Option<Point2>,
+    pub bounds: Option<Rect>,
 }
 
 impl Camera {
@@ -18,11 +21,26 @@ impl Camera {
             screen_size,
             speed: 100.0,
             focus: None,
+            bounds: None,
+        }
+    }
+
+    /// Keeps the view inside `bounds`, usually the map's pixel extent.
+    pub fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = Some(bounds);
+        self.clamp_center();
+    }
+
+    fn clamp_center(&mut self) {
+        if let Some(bounds) = self.bounds {
+            self.center.x = clamp_axis(self.center.x, bounds.x, bounds.w, self.screen_size.x);
+            self.center.y = clamp_axis(self.center.y, bounds.y, bounds.h, self.screen_size.y);
         }
     }
 
     pub fn update_screen(&mut self, w: f32, h: f32) {
         self.screen_size = Vector2::new(w, h);
+        self.clamp_center();
     }
 
     pub fn update_center(&mut self, dt: f32) {
@@ -34,6 +52,7 @@ impl Camera {
                 self.center += to_move;
             }
         }
+        self.clamp_center();
     }
 
     pub fn get_world_center(&self) -> Point2 {
@@ -48,5 +67,16 @@ impl Camera {
         if self.focus.is_some() {
             self.center = self.focus.unwrap();
         }
+        self.clamp_center();
+    }
+}
+
+/// Clamps one axis of the center so the screen stays within `start..start + len`,
+/// centering on the bounds when they're smaller than the screen.
+fn clamp_axis(center: f32, start: f32, len: f32, screen: f32) -> f32 {
+    if len <= screen {
+        start + len / 2.0
+    } else {
+        center.max(start + screen / 2.0).min(start + len - screen / 2.0)
     }
 }
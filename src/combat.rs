@@ -0,0 +1,25 @@
+use specs::Entity;
+
+use crate::Vector2;
+
+/// Something hitting something else, applied by `CombatSys`.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    // Whoever made the hit, pushing harder the heavier they are
+    pub attacker: Option<Entity>,
+    pub target: Entity,
+    pub damage: u8,
+    // Which way the hit was travelling, only its horizontal sign matters
+    pub direction: Vector2,
+}
+
+/// Hits queued up by other systems, drained every tick by `CombatSys`.
+pub struct HitEvents {
+    pub hits: Vec<Hit>,
+}
+
+impl HitEvents {
+    pub fn new() -> Self {
+        Self { hits: Vec::new() }
+    }
+}
@@ -1,22 +1,34 @@
 use specs::World;
 
 mod state;
+mod elevator;
 mod equipment;
+mod jump;
+mod nav;
 mod physics;
+mod pickup;
+mod projectile;
 mod position;
 mod render;
 mod sound;
 mod stats;
+mod vision;
 // mod turn;
 
 pub use self::{
     state::StateComp,
+    elevator::{ElevatorComp, ElevatorState},
     equipment::EquipmentComp,
+    jump::{JumpComp, JumpParams},
+    nav::NavComp,
     position::PositionComp,
-    render::{SpriteComp, AnimComp, WHITE, BLACK},
+    render::{SpriteComp, AnimComp, WHITE, BLACK, DIM},
     sound::{SoundType, SoundComp},
     stats::StatsComp,
-    physics::{PhysicsComp, CollideComp, PhysicsType},
+    vision::VisionComp,
+    physics::{PhysicsComp, CollideComp, PhysicsType, Contacts},
+    pickup::PickupComp,
+    projectile::ProjectileComp,
     // turn::{TurnPhase, TurnComp},
 };
 
@@ -30,5 +42,11 @@ pub fn register_components(world: &mut World) {
     world.register::<StateComp>();
     world.register::<StatsComp>();
     world.register::<EquipmentComp>();
+    world.register::<VisionComp>();
+    world.register::<ElevatorComp>();
+    world.register::<PickupComp>();
+    world.register::<JumpComp>();
+    world.register::<NavComp>();
+    world.register::<ProjectileComp>();
     // world.register::<TurnComp>();
 }
@@ -0,0 +1,37 @@
+use specs::HashMapStorage;
+
+use crate::map::MapPoint;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ElevatorState {
+    Idle,
+    Called,
+    Arriving,
+    Boarding,
+    Descending,
+}
+
+#[derive(Component)]
+#[component(HashMapStorage)]
+pub struct ElevatorComp {
+    pub state: ElevatorState,
+    // Seconds spent in the current state
+    pub timer: f32,
+    pub point: MapPoint,
+}
+
+impl ElevatorComp {
+    pub fn new(point: MapPoint) -> Self {
+        Self {
+            state: ElevatorState::Idle,
+            timer: 0.0,
+            point,
+        }
+    }
+
+    pub fn change_state(&mut self, state: ElevatorState) {
+        info!("Elevator changed state to {:?}", state);
+        self.state = state;
+        self.timer = 0.0;
+    }
+}
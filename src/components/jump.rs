@@ -0,0 +1,52 @@
+use specs::VecStorage;
+
+/// How a skirmer jumps, from its entry in "skirmers.ron".
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct JumpParams {
+    // Pixels per second straight up when the jump starts
+    pub speed: f32,
+    // What's left of the upward speed when the button is let go early
+    pub release_cut: f32,
+    // Seconds after walking off a ledge that a jump still works
+    pub coyote_time: f32,
+    // Seconds before landing that a press is remembered for
+    pub buffer_time: f32,
+}
+
+impl Default for JumpParams {
+    fn default() -> Self {
+        Self {
+            speed: 330.0,
+            release_cut: 0.4,
+            coyote_time: 0.1,
+            buffer_time: 0.12,
+        }
+    }
+}
+
+#[derive(Component)]
+#[component(VecStorage)]
+pub struct JumpComp {
+    pub params: JumpParams,
+    // Seconds left to jump since last on the ground
+    pub coyote: f32,
+    // Seconds left on a press that hasn't turned into a jump yet
+    pub buffer: f32,
+    // Jump was held last tick, so only new presses count
+    pub held: bool,
+    // Still going up from a jump that letting go can cut short
+    pub rising: bool,
+}
+
+impl JumpComp {
+    pub fn new(params: JumpParams) -> Self {
+        Self {
+            params,
+            coyote: 0.0,
+            buffer: 0.0,
+            held: false,
+            rising: false,
+        }
+    }
+}
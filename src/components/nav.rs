@@ -0,0 +1,25 @@
+use specs::HashMapStorage;
+
+use crate::map::{NavAction, NavProfile};
+
+/// A skirmer that finds its own way around the level, following a route
+/// through its `NavGraph` towards the player.
+#[derive(Component)]
+#[component(HashMapStorage)]
+pub struct NavComp {
+    pub profile: NavProfile,
+    // What's left of the route, the next action first
+    pub path: Vec<NavAction>,
+    // Seconds since the route was last planned
+    pub since_plan: f32,
+}
+
+impl NavComp {
+    pub fn new(profile: NavProfile) -> Self {
+        Self {
+            profile,
+            path: Vec::new(),
+            since_plan: std::f32::INFINITY,
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use specs::VecStorage;
 use ncollide2d::world::CollisionObjectHandle;
+use nalgebra::Isometry2;
 
-use crate::{Vector2};
+use crate::{
+    Point2, Vector2,
+    map::{TILE_WIDTH, TILE_HEIGHT},
+};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum PhysicsType {
@@ -9,12 +13,26 @@ pub enum PhysicsType {
     Constant,
 }
 
+/// Which sides of a body ran into a solid tile during its last move.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Contacts {
+    pub left: bool,
+    pub right: bool,
+    pub top: bool,
+    pub bottom: bool,
+}
+
 #[derive(Clone, Debug, Component)]
 #[component(VecStorage)]
 pub struct PhysicsComp {
     pub physics_type: PhysicsType,
+    // Pixels per second
     pub velocity: Vector2,
+    // Pixels per second per second, added up over a tick then cleared
     pub acceleration: Vector2,
+    // Heavier bodies are pushed less by the same impulse
+    pub mass: f32,
+    pub contacts: Contacts,
 }
 
 impl PhysicsComp {
@@ -23,8 +41,15 @@ impl PhysicsComp {
             physics_type,
             velocity: vel,
             acceleration: nalgebra::zero(),
+            mass: 1.0,
+            contacts: Contacts::default(),
         }
     }
+
+    /// Changes the velocity at once by `impulse` over the body's mass.
+    pub fn apply_impulse(&mut self, impulse: Vector2) {
+        self.velocity += impulse / self.mass;
+    }
 }
 
 #[derive(Clone, Debug, Component)]
@@ -32,3 +57,18 @@ impl PhysicsComp {
 pub struct CollideComp {
     pub handle: CollisionObjectHandle,
 }
+
+impl CollideComp {
+    /// Where the collider of an entity drawn at (`x`, `y`) goes, in the middle
+    /// of its sprite like the tiles' colliders.
+    pub fn isometry_at(x: f32, y: f32) -> Isometry2<f32> {
+        let offset = Vector2::new(TILE_WIDTH as f32 / 2.0, TILE_HEIGHT as f32 / 2.0);
+        Isometry2::new(Vector2::new(x, y) + offset, nalgebra::zero())
+    }
+
+    /// The sprite's top-left corner for a collider at `isometry`.
+    pub fn sprite_position(isometry: &Isometry2<f32>) -> Point2 {
+        let center = isometry.translation.vector;
+        Point2::new(center.x - TILE_WIDTH as f32 / 2.0, center.y - TILE_HEIGHT as f32 / 2.0)
+    }
+}
@@ -0,0 +1,14 @@
+use specs::HashMapStorage;
+
+/// An item lying on the map waiting to be picked up.
+#[derive(Component)]
+#[component(HashMapStorage)]
+pub struct PickupComp {
+    pub item: String,
+}
+
+impl PickupComp {
+    pub fn new(item: String) -> Self {
+        Self { item }
+    }
+}
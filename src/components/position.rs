@@ -5,10 +5,27 @@ use specs::VecStorage;
 pub struct PositionComp {
     pub x: f32,
     pub y: f32,
+    // Where the entity was at the end of the last simulation tick, for drawing
+    // it in between ticks
+    pub prev_x: f32,
+    pub prev_y: f32,
 }
 
 impl PositionComp {
     pub fn new(x: f32, y: f32) -> Self {
-        Self { x, y }
+        Self { x, y, prev_x: x, prev_y: y }
+    }
+
+    /// Moves straight to (`x`, `y`) without drawing the entity sliding there.
+    pub fn teleport(&mut self, x: f32, y: f32) {
+        *self = Self::new(x, y);
+    }
+
+    /// The position `alpha` of the way from the last tick to this one.
+    pub fn interpolated(&self, alpha: f32) -> (f32, f32) {
+        (
+            self.prev_x + (self.x - self.prev_x) * alpha,
+            self.prev_y + (self.y - self.prev_y) * alpha,
+        )
     }
 }
@@ -0,0 +1,28 @@
+use specs::{Entity, HashMapStorage};
+
+/// A shot in flight, spent when it hits something or runs out of range.
+#[derive(Component)]
+#[component(HashMapStorage)]
+pub struct ProjectileComp {
+    // Whoever fired it, who it can't hit
+    pub owner: Entity,
+    pub damage: u8,
+    // Pixels per second per second pulling it down
+    pub gravity: f32,
+    // Pixels it can still fly
+    pub range_left: f32,
+    // Tiles its blast reaches when it hits something, or 0 for none
+    pub blast_radius: i32,
+}
+
+impl ProjectileComp {
+    pub fn new(owner: Entity, damage: u8, gravity: f32, range: f32, blast_radius: i32) -> Self {
+        Self {
+            owner,
+            damage,
+            gravity,
+            range_left: range,
+            blast_radius,
+        }
+    }
+}
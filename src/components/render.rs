@@ -4,6 +4,8 @@ use ggez::graphics::Color;
 
 pub const WHITE: Color = Color { r: 0.921, g: 0.859, b: 0.698, a: 1.0};
 pub const BLACK: Color = Color { r: 0.157, g: 0.157, b: 0.157, a: 1.0 };
+// Tint for things remembered but not currently in sight
+pub const DIM: Color = Color { r: 0.368, g: 0.344, b: 0.279, a: 1.0 };
 
 #[derive(Component)]
 #[component(VecStorage)]
@@ -11,6 +11,14 @@ pub struct StateComp {
     pub move_action: InputState,
     pub attack_action: Option<MapPoint>,
     pub on_ground: bool,
+    // Standing on a platform or slope rather than a solid tile
+    pub on_surface: bool,
+    // Seconds left falling through platforms after pressing down on one
+    pub drop_timer: f32,
+    // Holding on to a ladder or vine, out of gravity's reach
+    pub climbing: bool,
+    // Seconds left reeling from a hit, unable to walk, jump or climb
+    pub hit_stun: f32,
 }
 
 impl StateComp {
@@ -19,6 +27,10 @@ impl StateComp {
             move_action: InputState::new(),
             attack_action: None,
             on_ground: false,
+            on_surface: false,
+            drop_timer: 0.0,
+            climbing: false,
+            hit_stun: 0.0,
         }
     }
 
@@ -35,7 +47,11 @@ impl StateComp {
     }
 
     pub fn is_on_ground(&self) -> bool {
-        self.on_ground
+        self.on_ground || self.on_surface
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.hit_stun > 0.0
     }
 }
 
@@ -1,5 +1,7 @@
 use specs::VecStorage;
 
+use crate::systems::MOVE_SPEED;
+
 #[derive(Component)]
 #[component(VecStorage)]
 pub struct StatsComp {
@@ -8,9 +10,22 @@ pub struct StatsComp {
     pub strength: u8,
     pub aim: u8,
     pub move_per_turn: u8,
+    // Pixels per second when walking
+    pub run_speed: f32,
 }
 
 impl StatsComp {
+    pub fn new(max_health: u8, strength: u8, aim: u8, run_speed: f32) -> Self {
+        Self {
+            health: max_health,
+            max_health,
+            strength,
+            aim,
+            run_speed,
+            ..Self::default()
+        }
+    }
+
     pub fn default() -> Self {
         Self {
             health: 100,
@@ -18,6 +33,7 @@ impl StatsComp {
             strength: 5,
             aim: 5,
             move_per_turn: 7,
+            run_speed: MOVE_SPEED,
         }
     }
 }
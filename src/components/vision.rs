@@ -0,0 +1,28 @@
+use specs::HashMapStorage;
+
+use std::collections::HashSet;
+
+use crate::map::MapPoint;
+
+#[derive(Component)]
+#[component(HashMapStorage)]
+pub struct VisionComp {
+    pub radius: i32,
+    pub visible: HashSet<MapPoint>,
+    pub explored: HashSet<MapPoint>,
+}
+
+impl VisionComp {
+    pub fn new(radius: i32) -> Self {
+        Self {
+            radius,
+            visible: HashSet::new(),
+            explored: HashSet::new(),
+        }
+    }
+
+    pub fn forget(&mut self) {
+        self.visible.clear();
+        self.explored.clear();
+    }
+}
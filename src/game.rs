@@ -4,19 +4,23 @@ use ggez::graphics::{Rect};
 use specs::{World, Dispatcher, DispatcherBuilder, RunNow, Entity};
 
 use std::collections::HashMap;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::{
-    Point2, CollisionWorld, CollisionObject,
+    Point2, CollisionWorld,
     asset_storage::AssetStorage,
     camera::Camera,
     components::*,
     systems::*,
-    resources::DeltaTime,
+    resources::{DeltaTime, FixedTimestep, Interpolation, duration_secs},
     input::PlayerInputState,
     item::ItemFactory,
+    level::{LevelSequence, nav_profile, populate_level, replace_level, place_player},
+    hot_reload::HotReloader,
+    combat::HitEvents,
+    spatial::SpatialQuery,
     skirmer::{SkirmerFactory, SkirmerType::Fighter},
-    map::{MapPoint, SkirmMap},
+    map::{MapPoint, SkirmMap, TerrainEdit, TerrainEdits, TerrainEvents, TileType, LevelGenerator, NavGraphs, AutotileRules, DEFAULT_AUTOTILE_PATH, TILE_WIDTH, TILE_HEIGHT},
     // gui::{Gui},
     visual_effects::{GunshotEffect, GunshotEffects},
 };
@@ -25,32 +29,58 @@ use crate::SkirmResult;
 
 pub const PLAYER_COLLISION_GROUP: usize = 1;
 pub const TILE_COLLISION_GROUP: usize = 2;
+pub const PROJECTILE_COLLISION_GROUP: usize = 3;
+
+const PLAYER_VISION_RADIUS: i32 = 8;
+
+// Pixel size of each spatial hash cell, a few tiles across
+const SPATIAL_CELL_SIZE: f32 = 128.0;
+
+const RESOURCE_DIR: &str = "./resources";
+// Where development mode saves the map being played
+const SAVED_MAP_DIR: &str = "./resources/maps/saved";
+
+// Tiles from the player that rock can be dug out or put back
+const DIG_REACH: i32 = 1;
+
+// Simulation ticks per second, whatever the frame rate
+const TICK_RATE: u32 = 60;
+// Ticks one slow frame can catch up on, past that the game slows down instead
+const MAX_TICKS_PER_FRAME: u32 = 5;
+
+// Size in tiles of every generated level below the first
+const LEVEL_WIDTH: i32 = 40;
+const LEVEL_HEIGHT: i32 = 60;
 
 pub struct Game<'a, 'b> {
     world: World,
     p1_ent: Entity,
+    // Only in development mode
+    hot_reload: Option<HotReloader>,
     // pub gui: Gui,
     dispatcher: Dispatcher<'a, 'b>,
     has_focus: bool,
     paused: bool,
     camera: Camera,
+    timestep: FixedTimestep,
 }
 
 impl<'a, 'b> Game<'a, 'b> {
-    pub fn new(ctx: &mut Context) -> SkirmResult<Self> {
+    pub fn new(ctx: &mut Context, dev_mode: bool) -> SkirmResult<Self> {
         let mut world = World::new();
         register_components(&mut world);
 
         info!("Build storage and skirmer/item factories");
         let mut asset_storage = AssetStorage::new(ctx)?;
         let item_factory = ItemFactory::new()?;
-        let skirmer_factory = SkirmerFactory::new();
+        let skirmer_factory = SkirmerFactory::new()?;
 
         info!("Create collision world");
         let collide_world: CollisionWorld = CollisionWorld::new(0.02);
         world.add_resource(collide_world);
+        world.add_resource(AutotileRules::load(DEFAULT_AUTOTILE_PATH)?);
 
-        let mut map = SkirmMap::load("./resources/maps/test.skirm_map", &mut world)?;
+        let mut map = SkirmMap::load("./resources/maps/test", &mut world)?;
 
         asset_storage.load_images(ctx)?;
         asset_storage.load_animations()?;
@@ -60,28 +90,58 @@ impl<'a, 'b> Game<'a, 'b> {
         ent1_sounds.insert(SoundType::Move, ("sine", true));
 
         info!("Create entities");
-        let p1_ent = skirmer_factory.create_skirmer(2, 2, &Fighter, &item_factory, &mut map, &mut world).unwrap();
+        let spawn = map.spawn;
+        let p1_ent = skirmer_factory.create_skirmer(spawn.x, spawn.y, &Fighter, &item_factory, &mut map, &mut world)?;
+        world.write::<VisionComp>().insert(p1_ent, VisionComp::new(PLAYER_VISION_RADIUS));
+        info!("Populate level {:?}", map.meta.name);
+        let placed = populate_level(&mut world, &mut map, &skirmer_factory, &item_factory);
+
+        let profile = nav_profile(&world, p1_ent).expect("The player can jump");
+        let mut levels = LevelSequence::new(run_seed(), LevelGenerator::new(LEVEL_WIDTH, LEVEL_HEIGHT, profile));
+        levels.entities = placed;
 
         let gunshot_effects: Vec<GunshotEffect> = Vec::new();
 
         info!("Create camera");
-        let camera = Camera::new(250, 450);
+        let mut camera = Camera::new(250, 450);
+        camera.set_bounds(map.pixel_bounds());
 
         info!("Add specs shared resources");
         world.add_resource(asset_storage);
-        world.add_resource(DeltaTime { delta: Duration::new(0, 0) });
+        world.add_resource(DeltaTime { delta: tick_duration() });
+        world.add_resource(Interpolation { alpha: 1.0 });
         world.add_resource(PlayerInputState::new(p1_ent));
         world.add_resource(map);
         world.add_resource(GunshotEffects { effects: gunshot_effects });
+        world.add_resource(TerrainEdits::new());
+        world.add_resource(TerrainEvents::new());
+        world.add_resource(NavGraphs::new());
+        world.add_resource(HitEvents::new());
+        world.add_resource(SpatialQuery::new(SPATIAL_CELL_SIZE));
+        world.add_resource(levels);
+        world.add_resource(item_factory);
+        world.add_resource(skirmer_factory);
 
         info!("Build system dispatcher");
         let dispatcher: Dispatcher<'a, 'b> = DispatcherBuilder::new()
             .add(PlanSys, "plan", &[])
+            .add(ElevatorSys, "elevator", &["plan"])
             .add(StateSys, "act", &["plan"])
-            .add(StatsSys, "stats", &["act"])
+            .add(CombatSys, "combat", &["act"])
+            .add(FireSys, "fire", &["act"])
+            .add(StatsSys, "stats", &["combat"])
             .add(SoundSys, "sound", &["act"])
             .add(AnimSys, "anim", &["act"])
-            .add(PhysicsSys, "physics", &[])
+            .add(TerrainSys, "terrain", &["act"])
+            .add(AutotileSys, "autotile", &["terrain"])
+            .add(PhysicsSys, "physics", &["combat"])
+            .add(OccupancySys, "occupancy", &["physics"])
+            .add(SpatialSys, "spatial", &["physics", "terrain"])
+            .add(ProjectileSys, "projectiles", &["spatial", "fire", "combat"])
+            .add(PickupSys, "pickups", &["spatial"])
+            .add(VisionSys, "vision", &["physics", "terrain"])
+            .add(SecretSys, "secrets", &["vision"])
+            .add(NavSys, "nav", &["terrain"])
             .build();
 
         // info!("Build gui");
@@ -92,11 +152,13 @@ impl<'a, 'b> Game<'a, 'b> {
         Ok(Self {
             world,
             p1_ent,
+            hot_reload: if dev_mode { Some(HotReloader::new(RESOURCE_DIR)) } else { None },
             // gui,
             dispatcher,
             has_focus: true,
             paused: false,
             camera,
+            timestep: FixedTimestep::new(tick_duration(), MAX_TICKS_PER_FRAME),
         })
     }
 
@@ -107,97 +169,138 @@ impl<'a, 'b> Game<'a, 'b> {
         gun_effects.retain(|effect| !effect.finished());
     }
 
-    fn update_game(&mut self, ctx: &mut Context) {
+    /// Runs as many fixed ticks as the time since the last frame covers, so
+    /// the simulation plays out the same at any frame rate. What's left over
+    /// decides how far between ticks everything is drawn.
+    fn update_frame(&mut self, ctx: &mut Context) {
         self.print_fps_to_info(ctx);
 
-        // Update delta time for frame
-        let dt = &timer::get_delta(ctx);
-        self.world.write_resource::<DeltaTime>().delta = *dt;
+        let frame = timer::get_delta(ctx);
+        for _ in 0..self.timestep.advance(frame) {
+            run_tick(&mut self.world, &mut self.dispatcher);
+        }
+        self.world.write_resource::<Interpolation>().alpha = self.timestep.alpha();
 
-        self.handle_collisions();
+        self.update_level(duration_secs(&frame));
         self.update_camera(ctx);
+    }
 
-        info!("<- Dispatch the specs systems");
-        self.dispatcher.dispatch(&self.world.res);
-        info!("-> Dispatch the specs systems");
+    /// Level changes that happen between frames rather than inside a tick:
+    /// going down once the elevator arrives, and reloading changed files.
+    fn update_level(&mut self, dt: f32) {
+        if self.world.read_resource::<LevelSequence>().descend {
+            if let Err(e) = self.descend() {
+                error!("Failed to build the next level, {:?}", e);
+            }
+        }
 
-        // Perform specs maintenance, removing entities, etc.
-        self.world.maintain();
+        if let Some(reloader) = self.hot_reload.as_mut() {
+            let reloaded = reloader.update(&mut self.world, dt);
+            if let Some(animations) = reloaded.animations {
+                self.world.write_resource::<AssetStorage>().animations = animations;
+            }
+            if reloaded.map {
+                self.camera.set_bounds(self.world.read_resource::<SkirmMap>().pixel_bounds());
+            }
+        }
     }
 
+    /// Replaces the current level with the next one down, carrying the player
+    /// and everything they have over to its spawn point.
+    fn descend(&mut self) -> SkirmResult {
+        let seed = {
+            let mut levels = self.world.write_resource::<LevelSequence>();
+            levels.descend = false;
+            levels.depth += 1;
+            info!("Descending to depth {}", levels.depth);
+            levels.level_seed()
+        };
+
+        // Swap in the next level, keeping only the player
+        let generator = self.world.read_resource::<LevelSequence>().generator.clone();
+        let map = SkirmMap::generate(&generator, seed, &mut self.world)?;
+        replace_level(&mut self.world, map);
+
+        let mut map = self.world.write_resource::<SkirmMap>();
+        place_player(&self.world, self.p1_ent, map.spawn);
+        if let Err(e) = map.add_occupant(self.p1_ent, map.spawn) {
+            warn!("Couldn't occupy the spawn point, {:?}", e);
+        }
+        self.camera.set_bounds(map.pixel_bounds());
+        Ok(())
+    }
+
+    /// Queues up a dig or place at a tile within the player's reach, for
+    /// `TerrainSys` to apply next tick.
+    fn edit_terrain(&mut self, edit: TerrainEdit) {
+        let target = match edit {
+            TerrainEdit::Dig(point) | TerrainEdit::Place(point, _) => point,
+            _ => return,
+        };
+        let player_tile = self.world.read::<PositionComp>().get(self.p1_ent)
+            .map(|p| MapPoint::from_pixel_coord(p.x as i32 + TILE_WIDTH / 2, p.y as i32 + TILE_HEIGHT / 2, 0));
+        let in_reach = player_tile.map_or(false, |p| {
+            (p.x - target.x).abs() <= DIG_REACH && (p.y - target.y).abs() <= DIG_REACH
+        });
+        if in_reach {
+            self.world.write_resource::<TerrainEdits>().edits.push(edit);
+        }
+    }
+
+    /// Writes the map as it is now out to `SAVED_MAP_DIR`.
+    fn save_map(&self) {
+        match self.world.read_resource::<SkirmMap>().save(SAVED_MAP_DIR) {
+            Ok(()) => info!("Saved the map to {:?}", SAVED_MAP_DIR),
+            Err(e) => warn!("Couldn't save the map, {}", e),
+        }
+    }
+
+    /// Follows the player where it's drawn this frame, not where the last
+    /// tick left it.
     fn update_camera(&mut self, ctx: &mut Context) {
-        let time = self.world.read_resource::<DeltaTime>();
+        let alpha = self.world.read_resource::<Interpolation>().alpha;
         let pos_components = self.world.read::<PositionComp>();
         let player_input = self.world.read_resource::<PlayerInputState>();
-        let player_pos = pos_components.get(player_input.ent).unwrap();
+        let (x, y) = pos_components.get(player_input.ent).unwrap().interpolated(alpha);
 
-        self.camera.focus = Some(Point2::new(player_pos.x, player_pos.y));
-        self.camera.update_center(time.as_dt());
+        self.camera.focus = Some(Point2::new(x, y));
+        self.camera.update_center(duration_secs(&timer::get_delta(ctx)));
     }
 
     fn print_fps_to_info(&self, ctx: &mut Context) {
         info!("FPS: {}", timer::get_fps(ctx));
     }
+}
 
-    fn handle_collisions(&self) {
-        info!("<- Checking collisions");
-        let mut col_world = self.world.write_resource::<CollisionWorld>();
-        let mut state_comps = self.world.write::<StateComp>();
-        col_world.update();
-
-        // Save and reuse the same vec each run of the loop so we only allocate once.
-        let contacts_list = &mut Vec::new();
-        for e in col_world.contact_events() {
-            contacts_list.clear();
-            match e {
-                ncollide2d::events::ContactEvent::Started(cobj_handle1, cobj_handle2) => {
-                    if let Some(pair) = (&*col_world).contact_pair(*cobj_handle1, *cobj_handle2) {
-                        println!("Starting collision between {:?} and {:?}", cobj_handle1, cobj_handle2);
-                        pair.contacts(contacts_list);
-                        let cobj1 = col_world.collision_object(*cobj_handle1).expect("Invalid collision object handle");
-                        let cobj2 = col_world.collision_object(*cobj_handle2).expect("Invalid collision object handle");
-
-                        let mut do_collision = |cobj1: &CollisionObject, cobj2: &CollisionObject| {
-                            let entity = cobj1.data();
-                            if cobj2.collision_groups().is_member_of(TILE_COLLISION_GROUP) {
-                                let entity_state = state_comps.get_mut(*entity).expect("Are collision groups blacklisted correctly?");
-                                entity_state.on_ground = true;
-                            }
-                        };
-                        do_collision(cobj1, cobj2);
-                        do_collision(cobj2, cobj1);
-                    }
-                }
-                ncollide2d::events::ContactEvent::Stopped(cobj_handle1, cobj_handle2) => {
-                    if let Some(pair) = (&*col_world).contact_pair(*cobj_handle1, *cobj_handle2) {
-                        println!("Finishing collision between {:?} and {:?}", cobj_handle1, cobj_handle2);
-                        pair.contacts(contacts_list);
-                        let cobj1 = col_world.collision_object(*cobj_handle1).expect("Invalid collision object handle");
-                        let cobj2 = col_world.collision_object(*cobj_handle2).expect("Invalid collision object handle");
-
-                        // Get the entities out of the collision data
-                        let mut do_collision = |cobj1: &CollisionObject, cobj2: &CollisionObject| {
-                            let entity = cobj1.data();
-                            if cobj2.collision_groups().is_member_of(TILE_COLLISION_GROUP) {
-                                let entity_state = state_comps.get_mut(*entity).expect("Are collision groups blacklisted correctly?");
-                                entity_state.on_ground = false;
-                            }
-                        };
-                        do_collision(cobj1, cobj2);
-                        do_collision(cobj2, cobj1);
-                    }
-                }
-            }
-        }
-        info!("-> Checking collisions");
-    }
+/// One fixed simulation tick.
+fn run_tick(world: &mut World, dispatcher: &mut Dispatcher) {
+    world.write_resource::<TerrainEvents>().events.clear();
+    PositionSys.run_now(&world.res);
+
+    // Bring the collision world's broad phase up to date for this tick.
+    // Bodies are kept out of tiles by `PhysicsSys`, which also decides who's
+    // on the ground.
+    info!("<- Checking collisions");
+    world.write_resource::<CollisionWorld>().update();
+    info!("-> Checking collisions");
+
+    info!("<- Dispatch the specs systems");
+    dispatcher.dispatch(&world.res);
+    info!("-> Dispatch the specs systems");
+
+    // Perform specs maintenance, removing entities, etc.
+    world.maintain();
+}
+
+fn tick_duration() -> Duration {
+    Duration::new(0, 1_000_000_000 / TICK_RATE)
 }
 
 impl<'a, 'b> event::EventHandler for Game<'a, 'b> {
     fn update(&mut self, ctx: &mut Context) -> SkirmResult {
         if self.has_focus && !self.paused {
             info!("<- Update Game");
-            self.update_game(ctx);
+            self.update_frame(ctx);
             info!("-> Update Game");
         }
 
@@ -213,7 +316,7 @@ impl<'a, 'b> event::EventHandler for Game<'a, 'b> {
 
         let pos_components = self.world.read::<PositionComp>();
         let player_pos = pos_components.get(player_input.ent).unwrap();
-        let pos = MapPoint::from_pixel_coord(player_pos.x as i32, player_pos.y as i32);
+        let pos = MapPoint::from_pixel_coord(player_pos.x as i32, player_pos.y as i32, 0);
 
         graphics::clear(ctx);
 
@@ -237,7 +340,13 @@ impl<'a, 'b> event::EventHandler for Game<'a, 'b> {
     }
 
     fn key_down_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
-        let mut input = self.world.write_resource::<PlayerInputState>().input;
+        if keycode == Keycode::F5 && self.hot_reload.is_some() {
+            self.save_map();
+            return;
+        }
+
+        let mut player_input = self.world.write_resource::<PlayerInputState>();
+        let input = &mut player_input.input;
 
         match keycode {
             Keycode::W => input.up.set(true),
@@ -249,7 +358,8 @@ impl<'a, 'b> event::EventHandler for Game<'a, 'b> {
     }
 
     fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
-        let mut input = self.world.write_resource::<PlayerInputState>().input;
+        let mut player_input = self.world.write_resource::<PlayerInputState>();
+        let input = &mut player_input.input;
 
         match keycode {
             Keycode::W => input.up.set(false),
@@ -265,18 +375,27 @@ impl<'a, 'b> event::EventHandler for Game<'a, 'b> {
     }
 
     fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: i32, y: i32) {
-        // let mut input = self.world.write_resource::<InputState>();
-
         // if self.gui.handle_click(Point2::new(x as f32, y as f32)) {
         //     return
         // }
 
-        // match button {
-        //     MouseButton::Left => input.select_point(x, y),
-        //     MouseButton::Right => (),
-        //     MouseButton::Middle => (),
-        //     _ => (),
-        // }
+        let cam = self.camera.get_world_center();
+        let target = MapPoint::from_pixel_coord(x + cam.x as i32, y + cam.y as i32, 0);
+        match button {
+            // Shoot at the tile under the cursor
+            MouseButton::Left => self.world.write_resource::<PlayerInputState>().attack = Some(target),
+            // Pull a chunk of rock out next to the player, or put one back
+            MouseButton::Right => self.edit_terrain(TerrainEdit::Dig(target)),
+            MouseButton::Middle => {
+                let wall = self.world.read_resource::<SkirmMap>().legend
+                    .find_glyph(|entry| entry.solid && entry.tile_type == Some(TileType::Wall));
+                match wall {
+                    Some(glyph) => self.edit_terrain(TerrainEdit::Place(target, glyph)),
+                    None => warn!("The map's legend has no wall to place"),
+                }
+            },
+            _ => (),
+        }
     }
 
     fn mouse_button_up_event(&mut self, _ctx: &mut Context, _button: MouseButton, x: i32, y: i32) {
@@ -299,3 +418,85 @@ impl<'a, 'b> event::EventHandler for Game<'a, 'b> {
     // fn controller_button_up_event(&mut self, _btn: Button, _instance_id: i32) { ... }
     // fn quit_event(&mut self) -> bool { ... }
 }
+
+/// A seed for the run's levels, different every time the game starts.
+fn run_seed() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => time.as_secs() ^ time.subsec_nanos() as u64,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A player on the test map holding right and jump, with just the
+    /// systems that move it.
+    fn simulation<'a, 'b>() -> (World, Dispatcher<'a, 'b>, Entity) {
+        let mut world = World::new();
+        register_components(&mut world);
+        world.add_resource(CollisionWorld::new(0.02));
+        world.add_resource(AutotileRules::default());
+
+        let items = ItemFactory::new().expect("Items load");
+        let skirmers = SkirmerFactory::new().expect("Skirmers load");
+        let mut map = SkirmMap::load("./resources/maps/test", &mut world).expect("Test map loads");
+        let spawn = map.spawn;
+        let player = skirmers.create_skirmer(spawn.x, spawn.y, &Fighter, &items, &mut map, &mut world)
+            .expect("Player is created");
+
+        let mut input = PlayerInputState::new(player);
+        input.input.right.set(true);
+        input.input.up.set(true);
+        world.add_resource(input);
+        world.add_resource(map);
+        world.add_resource(DeltaTime { delta: tick_duration() });
+        world.add_resource(TerrainEvents::new());
+
+        let dispatcher = DispatcherBuilder::new()
+            .add(PlanSys, "plan", &[])
+            .add(StateSys, "act", &["plan"])
+            .add(PhysicsSys, "physics", &["act"])
+            .build();
+        (world, dispatcher, player)
+    }
+
+    /// Frames at `fps` adding up to exactly `secs` seconds, the way a real
+    /// frame rate jitters by a nanosecond here and there.
+    fn frames(fps: u64, secs: u64) -> Vec<Duration> {
+        let end = |frame: u64| frame * 1_000_000_000 / fps;
+        (1..fps * secs + 1)
+            .map(|frame| Duration::from_nanos(end(frame) - end(frame - 1)))
+            .collect()
+    }
+
+    /// Plays `frames` through the fixed timestep, returning how many ticks ran
+    /// and where the player ended up.
+    fn play(frames: &[Duration]) -> (u32, (f32, f32), crate::Vector2) {
+        let (mut world, mut dispatcher, player) = simulation();
+        let mut timestep = FixedTimestep::new(tick_duration(), MAX_TICKS_PER_FRAME);
+        let mut ticks = 0;
+        for frame in frames {
+            for _ in 0..timestep.advance(*frame) {
+                run_tick(&mut world, &mut dispatcher);
+                ticks += 1;
+            }
+        }
+
+        let pos = world.read::<PositionComp>().get(player).map(|p| (p.x, p.y)).expect("Player has a position");
+        let velocity = world.read::<PhysicsComp>().get(player).map(|p| p.velocity).expect("Player has physics");
+        (ticks, pos, velocity)
+    }
+
+    #[test]
+    fn frame_rate_does_not_change_the_simulation() {
+        let slow = play(&frames(30, 1));
+        let fast = play(&frames(144, 1));
+        assert_eq!(slow.0, TICK_RATE);
+        assert_eq!(slow, fast);
+
+        let start = MapPoint::new(2, 2, 0).as_float_coord_tuple();
+        assert_ne!(slow.1, start, "The player should have moved");
+    }
+}
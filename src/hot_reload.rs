@@ -0,0 +1,302 @@
+//! Development mode: watches the resource directory and reloads whatever
+//! changes while the game runs. Nothing here needs a window, so it works
+//! against a bare `World` too.
+
+use specs::World;
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    asset_storage::read_animations,
+    components::{PositionComp, SpriteComp},
+    input::PlayerInputState,
+    item::ItemFactory,
+    level::{replace_level, place_player},
+    map::{AutotileRules, MapPoint, SkirmMap, TILE_WIDTH, TILE_HEIGHT},
+    skirmer::SkirmerFactory,
+};
+
+// Seconds between checks of the resource directory
+const POLL_INTERVAL: f32 = 0.5;
+
+/// Modification times of every file under a directory.
+pub struct AssetWatcher {
+    root: PathBuf,
+    mtimes: HashMap<PathBuf, SystemTime>,
+}
+
+impl AssetWatcher {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        let mut watcher = Self {
+            root: root.as_ref().to_path_buf(),
+            mtimes: HashMap::new(),
+        };
+        watcher.poll();
+        watcher
+    }
+
+    /// Files added or modified since the last poll.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut current = HashMap::new();
+        scan(&self.root, &mut current);
+        let changed = current.iter()
+            .filter(|(path, mtime)| self.mtimes.get(*path) != Some(*mtime))
+            .map(|(path, _)| path.clone())
+            .collect();
+        self.mtimes = current;
+        changed
+    }
+}
+
+fn scan(dir: &Path, mtimes: &mut HashMap<PathBuf, SystemTime>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Can't watch {:?}, {:?}", dir, e);
+            return;
+        },
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            scan(&path, mtimes);
+        } else if let Ok(mtime) = entry.metadata().and_then(|m| m.modified()) {
+            mtimes.insert(path, mtime);
+        }
+    }
+}
+
+/// What a round of reloading changed. Animations are handed back rather than
+/// applied, since `AssetStorage` only exists with a window.
+#[derive(Default)]
+pub struct Reloaded {
+    pub map: bool,
+    pub animations: Option<HashMap<String, Vec<String>>>,
+}
+
+pub struct HotReloader {
+    watcher: AssetWatcher,
+    // Seconds since the resource directory was last checked
+    since_poll: f32,
+}
+
+impl HotReloader {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            watcher: AssetWatcher::new(root),
+            since_poll: 0.0,
+        }
+    }
+
+    /// Checks for changes every `POLL_INTERVAL` seconds.
+    pub fn update(&mut self, world: &mut World, dt: f32) -> Reloaded {
+        self.since_poll += dt;
+        if self.since_poll < POLL_INTERVAL {
+            return Reloaded::default();
+        }
+        self.since_poll = 0.0;
+        self.reload_changed(world)
+    }
+
+    /// Reloads everything changed since the last check into `world`. A file
+    /// that fails to load is reported and the old version kept.
+    pub fn reload_changed(&mut self, world: &mut World) -> Reloaded {
+        let mut reloaded = Reloaded::default();
+        let mut map_changed = false;
+        for path in self.watcher.poll() {
+            match path.file_name().and_then(|name| name.to_str()) {
+                Some("weapons.ron") => match ItemFactory::load(&path) {
+                    Ok(items) => {
+                        info!("Reloaded {:?}", path);
+                        *world.write_resource::<ItemFactory>() = items;
+                    },
+                    Err(e) => warn!("Keeping the old items, {:?}", e),
+                },
+                Some("skirmers.ron") => match SkirmerFactory::load(&path) {
+                    Ok(skirmers) => {
+                        info!("Reloaded {:?}", path);
+                        *world.write_resource::<SkirmerFactory>() = skirmers;
+                    },
+                    Err(e) => warn!("Keeping the old skirmers, {:?}", e),
+                },
+                Some("autotile.ron") => match AutotileRules::load(&path) {
+                    Ok(rules) => {
+                        info!("Reloaded {:?}", path);
+                        *world.write_resource::<AutotileRules>() = rules;
+                        retile_map(world);
+                    },
+                    Err(e) => warn!("Keeping the old autotile rules, {:?}", e),
+                },
+                Some("animations.ron") => match read_animations(&path) {
+                    Ok(animations) => {
+                        info!("Reloaded {:?}", path);
+                        reloaded.animations = Some(animations);
+                    },
+                    Err(e) => warn!("Keeping the old animations, {:?}", e),
+                },
+                _ => map_changed |= is_part_of_map(world, &path),
+            }
+        }
+
+        if map_changed {
+            reloaded.map = reload_map(world);
+        }
+        reloaded
+    }
+}
+
+fn is_part_of_map(world: &World, path: &Path) -> bool {
+    let map = world.read_resource::<SkirmMap>();
+    match &map.source {
+        Some(source) => path.starts_with(source) || path == map.legend.path(),
+        None => false,
+    }
+}
+
+/// Picks every tile sprite of the current map again.
+fn retile_map(world: &World) {
+    let map = world.read_resource::<SkirmMap>();
+    let points = map.map.iter().map(|(point, _)| point);
+    map.retile(points, &world.read_resource::<AutotileRules>(), &mut world.write::<SpriteComp>());
+}
+
+/// Rebuilds the current map from disk in place. The player stays put unless
+/// that's now off the map or inside a wall, then it goes back to the spawn.
+fn reload_map(world: &mut World) -> bool {
+    let source = match world.read_resource::<SkirmMap>().source.clone() {
+        Some(source) => source,
+        None => return false,
+    };
+    let map = match SkirmMap::load_source(&source, world) {
+        Ok(map) => map,
+        Err(e) => {
+            warn!("Keeping the old map, {}", e);
+            return false;
+        },
+    };
+
+    info!("Reloaded map {:?}", source);
+    replace_level(world, map);
+
+    let player = world.read_resource::<PlayerInputState>().ent;
+    let center = world.read::<PositionComp>().get(player).map(|p| {
+        MapPoint::from_pixel_coord(p.x as i32 + TILE_WIDTH / 2, p.y as i32 + TILE_HEIGHT / 2, 0)
+    });
+    let spawn = world.read_resource::<SkirmMap>().spawn;
+    if center.map_or(false, |center| world.read_resource::<SkirmMap>().is_opaque(&center)) {
+        place_player(world, player, spawn);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::Entity;
+
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        CollisionWorld,
+        components::{register_components, JumpParams},
+        level::{populate_level, LevelSequence},
+        map::{LevelGenerator, NavGraphs, NavProfile, TerrainEdits, DEFAULT_AUTOTILE_PATH},
+        skirmer::SkirmerType,
+        systems::MOVE_SPEED,
+    };
+
+    const TEST_MAP: &str = "./resources/maps/test";
+
+    /// A copy of the test map under a fresh temp dir, returning the dir
+    /// watched and the map inside it.
+    fn temp_map(name: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(name);
+        if root.exists() {
+            fs::remove_dir_all(&root).expect("Old temp dir is removed");
+        }
+        let dir = root.join("test");
+        fs::create_dir_all(&dir).expect("Temp map dir is created");
+        for entry in fs::read_dir(TEST_MAP).expect("Test map is readable").filter_map(Result::ok) {
+            fs::copy(entry.path(), dir.join(entry.file_name())).expect("Test map file is copied");
+        }
+        (root, dir)
+    }
+
+    /// Everything the game keeps around a map, without a window, and the
+    /// player standing at its spawn.
+    fn world_with_map(dir: &Path) -> (World, Entity) {
+        let mut world = World::new();
+        register_components(&mut world);
+        world.add_resource(CollisionWorld::new(0.02));
+        world.add_resource(AutotileRules::load(DEFAULT_AUTOTILE_PATH).expect("Autotile rules load"));
+
+        let items = ItemFactory::new().expect("Items load");
+        let skirmers = SkirmerFactory::new().expect("Skirmers load");
+        let mut map = SkirmMap::load(dir, &mut world).expect("Temp map loads");
+        let spawn = map.spawn;
+        let player = skirmers.create_skirmer(spawn.x, spawn.y, &SkirmerType::Fighter, &items, &mut map, &mut world)
+            .expect("Player is created");
+        let placed = populate_level(&mut world, &mut map, &skirmers, &items);
+
+        let mut levels = LevelSequence::new(0, LevelGenerator::new(40, 60, NavProfile::from_jump(&JumpParams::default(), MOVE_SPEED)));
+        levels.entities = placed;
+        world.add_resource(map);
+        world.add_resource(levels);
+        world.add_resource(PlayerInputState::new(player));
+        world.add_resource(TerrainEdits::new());
+        world.add_resource(NavGraphs::new());
+        world.add_resource(items);
+        world.add_resource(skirmers);
+        (world, player)
+    }
+
+    /// Walls off an open tile of the test map's first z-level.
+    fn add_wall(dir: &Path, x: usize, y: usize) {
+        let path = dir.join("0.txt");
+        let text = fs::read_to_string(&path).expect("Level is readable");
+        let mut rows: Vec<Vec<char>> = text.lines().map(|row| row.chars().collect()).collect();
+        assert_eq!(rows[y][x], '.');
+        rows[y][x] = '#';
+        let text: String = rows.into_iter().map(|row| row.into_iter().collect::<String>() + "\n").collect();
+        fs::write(&path, text).expect("Level is written");
+    }
+
+    #[test]
+    fn edited_map_is_reported_and_reloaded() {
+        let (root, dir) = temp_map("aok_hot_reload_map");
+        let (mut world, player) = world_with_map(&dir);
+        let mut watcher = AssetWatcher::new(&root);
+        let mut reloader = HotReloader::new(&root);
+        let wall = MapPoint::new(10, 10, 0);
+        let position = {
+            let positions = world.read::<PositionComp>();
+            let pos = positions.get(player).expect("Player has a position");
+            (pos.x, pos.y)
+        };
+        assert!(watcher.poll().is_empty());
+        assert!(!world.read_resource::<SkirmMap>().is_opaque(&wall));
+
+        // Some filesystems only keep modification times to the second
+        thread::sleep(Duration::from_millis(1100));
+        add_wall(&dir, 10, 10);
+
+        assert_eq!(watcher.poll(), vec![dir.join("0.txt")]);
+        assert!(reloader.reload_changed(&mut world).map);
+
+        assert!(world.read_resource::<SkirmMap>().is_opaque(&wall));
+        assert_eq!(world.read_resource::<PlayerInputState>().ent, player);
+        assert!(world.is_alive(player));
+        let positions = world.read::<PositionComp>();
+        let pos = positions.get(player).expect("Player keeps its position");
+        assert_eq!((pos.x, pos.y), position);
+
+        fs::remove_dir_all(&root).ok();
+    }
+}
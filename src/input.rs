@@ -1,5 +1,7 @@
 use specs::Entity;
 
+use crate::map::MapPoint;
+
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub struct Input {
     pub handled: bool,
@@ -47,6 +49,8 @@ impl InputState {
 pub struct PlayerInputState {
     pub input: InputState,
     pub ent: Entity,
+    // The tile clicked to shoot at since the last tick
+    pub attack: Option<MapPoint>,
 }
 
 impl PlayerInputState {
@@ -54,6 +58,7 @@ impl PlayerInputState {
         Self {
             input: InputState::new(),
             ent,
+            attack: None,
         }
     }
 }
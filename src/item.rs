@@ -1,13 +1,18 @@
 use ron;
 
+use ggez::GameError;
+
 use std::{
     collections::HashMap,
     fs::File,
     io::Read,
+    path::Path,
 };
 
 use crate::SkirmResult;
 
+pub const WEAPONS_FILE: &str = "./resources/weapons.ron";
+
 pub enum Item {
 }
 
@@ -17,8 +22,17 @@ pub struct Weapon {
     pub description: String,
     pub damage: u8,
     pub accuracy: u8,
+    // Tiles a shot flies before it's spent
     pub range: u8,
     pub sound: String,
+    // Pixels per second leaving the barrel, and per second per second
+    // dropping after that
+    pub projectile_speed: f32,
+    pub projectile_gravity: f32,
+    // Tiles around where a shot lands that its blast opens secret walls in,
+    // most shots don't explode at all
+    #[serde(default)]
+    pub blast_radius: i32,
 }
 
 impl Weapon {
@@ -27,15 +41,19 @@ impl Weapon {
     }
 }
 
+#[derive(Clone)]
 pub struct ItemFactory {
     weapons: HashMap<String, Weapon>,
 }
 
 impl ItemFactory {
     pub fn new() -> SkirmResult<Self> {
+        Self::load(WEAPONS_FILE)
+    }
 
+    pub fn load<P: AsRef<Path>>(path: P) -> SkirmResult<Self> {
         // Weapons - open the file, read it into a buffer, deserialize with serde
-        let mut weapon_file = File::open("./resources/weapons.ron")?;
+        let mut weapon_file = File::open(path)?;
         let mut buffer = String::new();
         weapon_file.read_to_string(&mut buffer)?;
         let weapons: HashMap<String, Weapon> = match ron::de::from_str(buffer.as_str()) {
@@ -43,12 +61,23 @@ impl ItemFactory {
 
             // TODO In the future we could have some builtin weapons that don't
             // require .ron definition and use them here.
-            Err(e) => panic!("Error reading weapon.ron, format is corrupt. {:?}", e),
+            Err(e) => {
+                let msg = format!("Error reading weapon.ron, format is corrupt. {:?}", e);
+                return Err(GameError::ResourceLoadError(msg));
+            },
         };
 
         Ok(ItemFactory { weapons })
     }
 
+    pub fn has_weapon(&self, name: &str) -> bool {
+        self.weapons.contains_key(name)
+    }
+
+    pub fn find_weapon(&self, name: &str) -> Option<Weapon> {
+        self.weapons.get(name).cloned()
+    }
+
     pub fn get_weapon(&self, name: &'static str) -> Weapon {
         match self.weapons.get(name) {
             Some(weapon) => weapon.clone(),
@@ -0,0 +1,180 @@
+use specs::{Entity, Join, World};
+
+use crate::{
+    CollisionWorld,
+    components::*,
+    item::ItemFactory,
+    skirmer::{SkirmerFactory, SkirmerType},
+    map::{MapPoint, LevelGenerator, NavGraphs, NavProfile, SkirmMap, TerrainEdits},
+    systems::MOVE_SPEED,
+};
+
+/// Where the run is in its descent. Levels are only ever generated going down,
+/// there's no way back to an earlier one.
+pub struct LevelSequence {
+    pub depth: u32,
+    pub seed: u64,
+    pub generator: LevelGenerator,
+    // Set once the elevator has finished descending
+    pub descend: bool,
+    // Everything placed from the current level's metadata
+    pub entities: Vec<Entity>,
+}
+
+impl LevelSequence {
+    pub fn new(seed: u64, generator: LevelGenerator) -> Self {
+        Self {
+            depth: 0,
+            seed,
+            generator,
+            descend: false,
+            entities: Vec::new(),
+        }
+    }
+
+    pub fn level_seed(&self) -> u64 {
+        self.seed.wrapping_add(self.depth as u64)
+    }
+}
+
+/// Swaps the world's map for `map`, tearing down the old one's tiles and
+/// placed entities and populating the new one. Everything else, the player
+/// included, is left where it is.
+pub fn replace_level(world: &mut World, mut map: SkirmMap) {
+    world.write_resource::<SkirmMap>().destroy(world);
+    let placed = std::mem::replace(&mut world.write_resource::<LevelSequence>().entities, Vec::new());
+    clear_level(world, &placed);
+    world.write_resource::<TerrainEdits>().edits.clear();
+    world.write_resource::<NavGraphs>().clear();
+    world.maintain();
+
+    let skirmer_factory = world.read_resource::<SkirmerFactory>().clone();
+    let item_factory = world.read_resource::<ItemFactory>().clone();
+    let placed = populate_level(world, &mut map, &skirmer_factory, &item_factory);
+    world.write_resource::<LevelSequence>().entities = placed;
+    *world.write_resource::<SkirmMap>() = map;
+    world.maintain();
+}
+
+/// Moves the player onto `point` at a standstill, with its collider along and
+/// its memory of the old level gone.
+pub fn place_player(world: &World, ent: Entity, point: MapPoint) {
+    let (x, y) = point.as_float_coord_tuple();
+    if let Some(pos) = world.write::<PositionComp>().get_mut(ent) {
+        pos.teleport(x, y);
+    }
+    if let Some(physics) = world.write::<PhysicsComp>().get_mut(ent) {
+        physics.velocity = nalgebra::zero();
+        physics.acceleration = nalgebra::zero();
+    }
+    if let Some(state) = world.write::<StateComp>().get_mut(ent) {
+        state.on_ground = false;
+        state.on_surface = false;
+        state.climbing = false;
+        state.hit_stun = 0.0;
+    }
+    if let Some(jump) = world.write::<JumpComp>().get_mut(ent) {
+        jump.coyote = 0.0;
+        jump.buffer = 0.0;
+        jump.rising = false;
+    }
+    if let Some(vision) = world.write::<VisionComp>().get_mut(ent) {
+        vision.forget();
+    }
+    if let Some(collider) = world.read::<CollideComp>().get(ent) {
+        let position = CollideComp::isometry_at(x, y);
+        world.write_resource::<CollisionWorld>().set_position(collider.handle, position);
+    }
+}
+
+/// Creates the elevator, enemies and item pickups from the map's metadata,
+/// skipping any that name something that doesn't exist.
+pub fn populate_level(
+    world: &mut World,
+    map: &mut SkirmMap,
+    skirmer_factory: &SkirmerFactory,
+    item_factory: &ItemFactory,
+) -> Vec<Entity> {
+    let mut placed = Vec::new();
+    if let Some(point) = map.elevator {
+        placed.push(create_elevator(world, point));
+    }
+
+    for spawner in map.meta.enemy_spawners.clone() {
+        let skirmer = match SkirmerType::from_name(&spawner.skirmer) {
+            Some(skirmer) => skirmer,
+            None => {
+                warn!("Map {:?} spawns unknown skirmer {:?}", map.meta.name, spawner.skirmer);
+                continue;
+            },
+        };
+        match skirmer_factory.create_skirmer(spawner.point.x, spawner.point.y, &skirmer, item_factory, map, world) {
+            Ok(ent) => {
+                // Enemies find their own way to the player
+                let profile = nav_profile(world, ent);
+                if let Some(profile) = profile {
+                    world.write::<NavComp>().insert(ent, NavComp::new(profile));
+                }
+                placed.push(ent);
+            },
+            Err(e) => warn!("Couldn't spawn {:?} at {:?}, {:?}", spawner.skirmer, spawner.point, e),
+        }
+    }
+
+    for pickup in &map.meta.items {
+        if !item_factory.has_weapon(&pickup.item) {
+            warn!("Map {:?} places unknown item {:?}", map.meta.name, pickup.item);
+            continue;
+        }
+        placed.push(create_pickup(world, pickup.item.clone(), pickup.point));
+    }
+
+    placed
+}
+
+/// How `ent` gets around, from how it jumps and how fast it runs.
+pub fn nav_profile(world: &World, ent: Entity) -> Option<NavProfile> {
+    let run_speed = world.read::<StatsComp>().get(ent).map_or(MOVE_SPEED, |stats| stats.run_speed);
+    world.read::<JumpComp>().get(ent).map(|jump| NavProfile::from_jump(&jump.params, run_speed))
+}
+
+/// Deletes everything `populate_level` created that's still around and every
+/// shot still in the air, along with any colliders.
+pub fn clear_level(world: &World, placed: &[Entity]) {
+    let entities = world.entities();
+    let collide = world.read::<CollideComp>();
+    let projectiles = world.read::<ProjectileComp>();
+    let mut collide_world = world.write_resource::<CollisionWorld>();
+
+    let shots: Vec<Entity> = (&*entities, &projectiles).join().map(|(ent, _)| ent).collect();
+    for ent in placed.iter().chain(shots.iter()) {
+        // Pickups are gone once picked up, and enemies once killed
+        if !entities.is_alive(*ent) {
+            continue;
+        }
+        if let Some(collider) = collide.get(*ent) {
+            collide_world.remove(&[collider.handle]);
+        }
+        if let Err(e) = entities.delete(*ent) {
+            warn!("Level entity was already deleted, {:?}", e);
+        }
+    }
+}
+
+pub fn create_pickup(world: &mut World, item: String, point: MapPoint) -> Entity {
+    let (x, y) = point.as_float_coord_tuple();
+    world.create_entity()
+        .with(PositionComp::new(x, y))
+        .with(SpriteComp::new(String::from("checkerbox")))
+        .with(PickupComp::new(item))
+        .build()
+}
+
+pub fn create_elevator(world: &mut World, point: MapPoint) -> Entity {
+    let (x, y) = point.as_float_coord_tuple();
+    world.create_entity()
+        .with(PositionComp::new(x, y))
+        .with(SpriteComp::new(String::from("blue_box")))
+        .with(ElevatorComp::new(point))
+        .build()
+}
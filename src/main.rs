@@ -4,6 +4,8 @@ extern crate ggez;
 extern crate specs;
 extern crate serde;
 extern crate ron;
+extern crate serde_json;
+extern crate xml;
 extern crate ascii;
 extern crate pathfinding;
 extern crate line_drawing;
@@ -35,12 +37,15 @@ mod input;
 mod gui;
 mod visual_effects;
 mod camera;
+mod level;
+mod spatial;
+mod hot_reload;
+mod combat;
 
 use crate::game::Game;
 
 type SkirmResult<T = ()> = GameResult<T>;
 type CollisionWorld = ncollide2d::world::CollisionWorld<f32, specs::Entity>;
-type CollisionObject = ncollide2d::world::CollisionObject<f32, specs::Entity>;
 type Point2 = nalgebra::Point2<f32>;
 type Vector2 = nalgebra::Vector2<f32>;
 
@@ -56,8 +61,11 @@ fn main() -> SkirmResult {
         .build()
         .expect("Something went wrong building the game's context!");
 
+    // Development mode reloads maps and RON files as they change on disk
+    let dev_mode = std::env::args().any(|arg| arg == "--hot-reload");
+
     info!("Creating Game");
-    let mut game = Game::new(&mut ctx).expect("We screwed up creating the initial game state!");
+    let mut game = Game::new(&mut ctx, dev_mode).expect("We screwed up creating the initial game state!");
 
     info!("Starting main loop");
     event::run(&mut ctx, &mut game)
@@ -1,70 +1,466 @@
-use ncollide2d::{
-    shape::{Cuboid, ShapeHandle},
-    world::{CollisionGroups, GeometricQueryType},
-};
-
-use nalgebra::Isometry2;
-
 use std::{
-    fs::File,
-    path::Path,
+    fs::{self, File},
+    path::{Path, PathBuf},
     fmt::Debug,
-    io::{ BufRead, BufReader },
-    collections::HashMap,
+    io::{ BufRead, BufReader, Write },
+    collections::{HashMap, HashSet},
 };
 
-use specs::Entity;
-use ascii::{ToAsciiChar, AsciiChar};
+use ggez::graphics::Rect;
+use specs::{Entity, WriteStorage};
+use ascii::ToAsciiChar;
 use pathfinding::dijkstra;
 use line_drawing;
 
 pub mod point;
 pub mod tile;
+pub mod legend;
+pub mod error;
+pub mod collider;
+pub mod terrain;
+pub mod grid;
+pub mod nav;
+pub mod fov;
+pub mod generate;
+pub mod tiled;
+pub mod meta;
+pub mod autotile;
 pub use self::point::MapPoint;
-pub use self::tile::{Tile, TileType};
+pub use self::tile::{Tile, TileType, Slope};
+pub use self::legend::{Legend, LegendEntry};
+pub use self::error::{MapError, FileLocation};
+pub use self::collider::{TileCollider, TileColliders, TileRect};
+pub use self::grid::TileGrid;
+pub use self::nav::{NavAction, NavGraph, NavGraphs, NavProfile};
+pub use self::generate::{LevelGenerator, GeneratedLevel};
+pub use self::tiled::TiledLevel;
+pub use self::meta::{MapMeta, EnemySpawner, ItemPickup};
+pub use self::autotile::{AutotileRules, Neighbourhood, Tileset, DEFAULT_AUTOTILE_PATH};
+pub use self::terrain::{TerrainAccess, TerrainEdit, TerrainEdits, TerrainEvent, TerrainEvents};
 
 use crate::{
-    SkirmResult,
-    game::TILE_COLLISION_GROUP,
     CollisionWorld,
-    Vector2,
     components::*,
 };
 
 pub const TILE_WIDTH: i32 = 32;
 pub const TILE_HEIGHT: i32 = 32;
 
-#[derive(PartialEq, Eq, Debug)]
-pub enum MapError {
-    Occupied,
-    PointDoesNotExist,
-}
+const LEGEND_FILE: &str = "legend.ron";
+const META_FILE: &str = "meta.ron";
+const DEFAULT_LEGEND_PATH: &str = "./resources/maps/legend.ron";
+
+/// What a dug out tile turns into.
+const DUG_GLYPH: char = '.';
 
 #[derive(Debug)]
 pub struct SkirmMap {
-    pub map: HashMap<MapPoint, Tile>,
+    pub map: TileGrid,
+    pub legend: Legend,
+    pub meta: MapMeta,
+    // The directory or Tiled file the map was loaded from, generated maps have none
+    pub source: Option<PathBuf>,
+    // Where the player starts, the first of the metadata's player spawns
+    pub spawn: MapPoint,
+    pub elevator: Option<MapPoint>,
+    // The z-level being played, the only one whose tiles are drawn
+    pub current_z: i32,
+    pub colliders: TileColliders,
+    // Seconds each secret wall has been looked at from up close
+    pub secret_linger: HashMap<MapPoint, f32>,
+    // Every entity touching each tile, kept up to date by OccupancySys
+    pub overlapping: HashMap<MapPoint, Vec<Entity>>,
 }
 
 impl SkirmMap {
-    pub fn load<P>(path: P, world: &mut specs::World) -> SkirmResult<Self>
+    /// Loads a map directory, stacking "0.txt", "1.txt", ... as z-levels, along
+    /// with the spawns and placements in its "meta.ron".
+    pub fn load<P>(dir: P, world: &mut specs::World) -> Result<Self, MapError>
         where P: AsRef<Path> + Debug,
     {
-        let map_file = File::open(path)?;
-        let buffer = BufReader::new(map_file);
-
-        let mut map = HashMap::new();
-        for (j, line) in buffer.lines().enumerate() {
-            for (i, c) in line.unwrap().chars().enumerate() {
-                if c.to_ascii_char().unwrap() == AsciiChar::Hash {
-                    create_map_entity(world, &mut map, i as i32, j as i32, Some(TileType::Ground));
-                }
-                else {
-                    create_map_entity(world, &mut map, i as i32, j as i32, None);
+        let dir = dir.as_ref();
+        let base_level = level_path(dir, 0);
+        if !base_level.is_file() {
+            return Err(MapError::MissingBaseLevel(base_level));
+        }
+
+        // A map may ship its own legend, otherwise the shared one is used
+        let legend_path = dir.join(LEGEND_FILE);
+        let legend = if legend_path.is_file() {
+            Legend::load(legend_path)?
+        } else {
+            Legend::load(DEFAULT_LEGEND_PATH)?
+        };
+
+        // Read and check every level before any entities are created. Each one
+        // has to be as wide as the first row of "0.txt" and as tall as it.
+        let base = read_level(&base_level, &legend)?;
+        let width = base.first().map_or(0, |row| row.len());
+        let height = base.len();
+        check_level_size(&base_level, width, height, &base)?;
+
+        let mut levels = vec![base];
+        while level_path(dir, levels.len() as i32).is_file() {
+            let path = level_path(dir, levels.len() as i32);
+            let level = read_level(&path, &legend)?;
+            check_level_size(&path, width, height, &level)?;
+            levels.push(level);
+        }
+
+        let meta_path = dir.join(META_FILE);
+        let mut meta = if meta_path.is_file() {
+            let meta = MapMeta::load(&meta_path)?;
+            check_meta_points(&meta_path, &meta, &levels, &legend)?;
+            meta
+        } else {
+            let name = dir.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+            MapMeta::new(name)
+        };
+        meta.add_markers(&levels, &legend);
+        if meta.player_spawns.is_empty() {
+            return Err(MapError::NoSpawnPoint(dir.to_path_buf()));
+        }
+
+        let mut map = Self::from_levels(&levels, legend, meta, world);
+        map.source = Some(dir.to_path_buf());
+        Ok(map)
+    }
+
+    /// Writes the map back out in the directory format `load` reads, one text
+    /// file per z-level plus its metadata. Dug out tiles and opened secret
+    /// walls are kept, every tile keeps its glyph, and a map with its own
+    /// legend gets a copy of it. Loading the result gives back the same map.
+    pub fn save<P>(&self, dir: P) -> Result<(), MapError>
+        where P: AsRef<Path> + Debug,
+    {
+        let dir = dir.as_ref();
+        let io_error = |path: &Path, e: std::io::Error| MapError::Io(path.to_path_buf(), e.to_string());
+        fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+
+        for z in 0..self.levels() {
+            let path = level_path(dir, z);
+            let mut level_file = File::create(&path).map_err(|e| io_error(&path, e))?;
+            level_file.write_all(self.level_text(z).as_bytes()).map_err(|e| io_error(&path, e))?;
+        }
+
+        // Left over levels from whatever was saved here before would be
+        // stacked on top when loading
+        let mut z = self.levels();
+        while level_path(dir, z).is_file() {
+            let path = level_path(dir, z);
+            fs::remove_file(&path).map_err(|e| io_error(&path, e))?;
+            z += 1;
+        }
+
+        let meta_path = dir.join(META_FILE);
+        let meta = ron::ser::to_string_pretty(&self.meta, ron::ser::PrettyConfig::default())
+            .map_err(|e| MapError::BadMeta(meta_path.clone(), format!("{:?}", e)))?;
+        fs::write(&meta_path, meta).map_err(|e| io_error(&meta_path, e))?;
+
+        // Maps with their own legend need it alongside them
+        let legend_path = dir.join(LEGEND_FILE);
+        let shared = self.legend.path() == Path::new(DEFAULT_LEGEND_PATH);
+        let in_place = fs::canonicalize(&legend_path).ok() == fs::canonicalize(self.legend.path()).ok();
+        if !shared && !in_place {
+            fs::copy(self.legend.path(), &legend_path).map_err(|e| io_error(&legend_path, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// One z-level as text, every tile written with its own glyph. Spawns
+    /// and the elevator are kept in the metadata, so drawn markers stay as
+    /// they were loaded.
+    fn level_text(&self, z: i32) -> String {
+        let (width, height) = self.extent();
+        let mut text = String::with_capacity(((width + 1) * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let point = MapPoint::new(x, y, z);
+                text.push(self.map.get(&point).expect("Point is within the map's extent").glyph);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Imports a level made in Tiled from a JSON or TMX file. A "legend.ron"
+    /// next to the file is used over the shared legend.
+    pub fn import_tiled<P>(path: P, world: &mut specs::World) -> Result<Self, MapError>
+        where P: AsRef<Path> + Debug,
+    {
+        let path = path.as_ref();
+        let legend_path = path.with_file_name(LEGEND_FILE);
+        let legend = if legend_path.is_file() {
+            Legend::load(legend_path)?
+        } else {
+            Legend::load(DEFAULT_LEGEND_PATH)?
+        };
+
+        let mut level = tiled::import(path, &legend)?;
+        level.meta.add_markers(&level.levels, &legend);
+        if level.meta.player_spawns.is_empty() {
+            return Err(MapError::NoSpawnPoint(path.to_path_buf()));
+        }
+
+        let mut map = Self::from_levels(&level.levels, legend, level.meta, world);
+        map.source = Some(path.to_path_buf());
+        Ok(map)
+    }
+
+    /// Loads a map from either a map directory or a Tiled file, like the
+    /// `source` of an already loaded map.
+    pub fn load_source(source: &Path, world: &mut specs::World) -> Result<Self, MapError> {
+        if source.is_dir() {
+            Self::load(source, world)
+        } else {
+            Self::import_tiled(source, world)
+        }
+    }
+
+    /// Generates a new level from `seed` using the shared legend.
+    pub fn generate(generator: &LevelGenerator, seed: u64, world: &mut specs::World) -> Result<Self, MapError> {
+        let legend = Legend::load(DEFAULT_LEGEND_PATH)?;
+        let level = generator.generate(seed, &legend)?;
+        let levels = [level.rows];
+
+        let mut meta = MapMeta::new(format!("Cavern {:x}", seed));
+        meta.add_markers(&levels, &legend);
+        if meta.player_spawns.is_empty() {
+            meta.player_spawns.push(level.spawn);
+        }
+
+        Ok(Self::from_levels(&levels, legend, meta, world))
+    }
+
+    /// Builds a map from rows of glyphs, one set per z-level, that have already
+    /// been checked against `legend`, and creates its entities and colliders.
+    /// `meta` needs at least one player spawn, and tile sprites are picked by
+    /// the world's `AutotileRules`.
+    pub fn from_levels(levels: &[Vec<Vec<char>>], legend: Legend, meta: MapMeta, world: &mut specs::World) -> Self {
+        let mut map = TileGrid::from_glyphs(levels, &legend);
+        let sprites: Vec<(MapPoint, Option<String>)> = {
+            let rules = world.read_resource::<AutotileRules>();
+            map.iter().map(|(point, _)| (point, rules.sprite_at(&map, &legend, &point))).collect()
+        };
+        for (point, sprite) in sprites {
+            let tile = map.get_mut(&point).expect("Points come from the grid itself");
+            tile.entity = create_tile_entity(world, &point, sprite);
+        }
+
+        let colliders = create_map_colliders(world, &map);
+
+        Self {
+            map,
+            legend,
+            spawn: meta.player_spawns[0],
+            elevator: meta.elevator,
+            current_z: 0,
+            meta,
+            source: None,
+            colliders,
+            secret_linger: HashMap::new(),
+            overlapping: HashMap::new(),
+        }
+    }
+
+    /// Deletes the map's tile entities and colliders from `world`, ready for
+    /// the next level to replace it.
+    pub fn destroy(&mut self, world: &specs::World) {
+        let entities = world.entities();
+        for (_, tile) in self.map.iter() {
+            if let Some(ent) = tile.entity {
+                if let Err(e) = entities.delete(ent) {
+                    warn!("Tile entity was already deleted, {:?}", e);
                 }
             }
         }
 
-        Ok(Self { map })
+        self.colliders.clear(
+            &entities,
+            &mut world.write::<CollideComp>(),
+            &mut world.write_resource::<CollisionWorld>(),
+        );
+    }
+
+    /// Width and height of the map in tiles.
+    pub fn extent(&self) -> (i32, i32) {
+        (self.map.width(), self.map.height())
+    }
+
+    /// The area the map covers in pixels, for the camera to stay within.
+    pub fn pixel_bounds(&self) -> Rect {
+        let (w, h) = self.extent();
+        Rect::new(0.0, 0.0, (w * TILE_WIDTH) as f32, (h * TILE_HEIGHT) as f32)
+    }
+
+    pub fn levels(&self) -> i32 {
+        self.map.levels()
+    }
+
+    pub fn in_bounds(&self, point: &MapPoint) -> bool {
+        self.map.in_bounds(point)
+    }
+
+    /// Removes the solid tile at `point`, leaving ground behind.
+    pub fn dig(&mut self, point: &MapPoint, access: &mut TerrainAccess) -> Result<(), MapError> {
+        match self.map.get(point) {
+            Some(tile) if tile.solid => (),
+            Some(_) => return Err(MapError::NotSolid),
+            None => return Err(MapError::PointDoesNotExist),
+        }
+        self.set_tile(point, DUG_GLYPH, access)
+    }
+
+    /// Places the legend's `glyph` at `point`, which must be open and unoccupied.
+    /// A solid glyph also can't go where any entity overlaps the tile, or it
+    /// would be sealed inside.
+    pub fn place(&mut self, point: &MapPoint, glyph: char, access: &mut TerrainAccess) -> Result<(), MapError> {
+        match self.map.get(point) {
+            Some(tile) if tile.solid || tile.has_occupant() => return Err(MapError::Occupied),
+            Some(_) => (),
+            None => return Err(MapError::PointDoesNotExist),
+        }
+        let solid = self.legend.get(glyph).map_or(false, |entry| entry.solid);
+        if solid && !self.entities_at(point).is_empty() {
+            return Err(MapError::Occupied);
+        }
+        self.set_tile(point, glyph, access)
+    }
+
+    /// A secret wall that nothing has revealed yet. These should be treated as
+    /// plain walls by anything shown to the player.
+    pub fn is_secret(&self, point: &MapPoint) -> bool {
+        self.map.get(point).map_or(false, |tile| tile.tile_type == Some(TileType::SecretWall))
+    }
+
+    /// Strength needed to smash the secret wall at `point` open by walking
+    /// into it, if it can be smashed at all.
+    pub fn smash_strength(&self, point: &MapPoint) -> Option<u8> {
+        if !self.is_secret(point) {
+            return None;
+        }
+        self.map.get(point)
+            .and_then(|tile| self.legend.get(tile.glyph))
+            .and_then(|entry| entry.smash_strength)
+    }
+
+    /// Opens up the secret wall at `point` into a passage.
+    pub fn reveal(&mut self, point: &MapPoint, access: &mut TerrainAccess) -> Result<(), MapError> {
+        if !self.is_secret(point) {
+            return Err(MapError::NotSecret);
+        }
+        self.set_tile(point, DUG_GLYPH, access)?;
+        self.secret_linger.remove(point);
+        Ok(())
+    }
+
+    /// Reveals every secret wall within `radius` tiles of `center`.
+    pub fn explode(&mut self, center: &MapPoint, radius: i32, access: &mut TerrainAccess) -> Result<(), MapError> {
+        let min = MapPoint::new(center.x - radius, center.y - radius, center.z);
+        let max = MapPoint::new(center.x + radius, center.y + radius, center.z);
+        let secrets: Vec<MapPoint> = self.map.region(&min, &max)
+            .filter(|(point, tile)| {
+                tile.tile_type == Some(TileType::SecretWall) && tile_distance(center, *point) as i32 <= radius
+            })
+            .map(|(point, _)| point)
+            .collect();
+
+        for point in secrets {
+            self.reveal(&point, access)?;
+        }
+        Ok(())
+    }
+
+    fn set_tile(&mut self, point: &MapPoint, glyph: char, access: &mut TerrainAccess) -> Result<(), MapError> {
+        let entry = match self.legend.get(glyph) {
+            Some(entry) => entry.clone(),
+            None => return Err(MapError::NotInLegend(glyph)),
+        };
+        let old = match self.map.get(point) {
+            Some(tile) => *tile,
+            None => return Err(MapError::PointDoesNotExist),
+        };
+
+        let mut tile = Tile::new(glyph, &entry);
+        tile.occupant = old.occupant;
+        tile.entity = update_tile_entity(point, old.entity, &entry, access);
+
+        // The collision world is flat, so only the bottom z-level collides
+        if point.z == 0 && old.solid != tile.solid {
+            if tile.solid {
+                self.colliders.add_tile(point, access.entities, access.collide, access.collide_world);
+            } else {
+                self.colliders.remove_tile(point, access.entities, access.collide, access.collide_world);
+            }
+        }
+
+        *self.map.get_mut(point).expect("Tile was looked up above") = tile;
+        access.events.events.push(TerrainEvent { point: *point });
+        Ok(())
+    }
+
+    /// Gives the tile entities at `points` the sprites `rules` pick for them
+    /// now, after their neighbours have changed.
+    pub fn retile<I>(&self, points: I, rules: &AutotileRules, sprites: &mut WriteStorage<SpriteComp>)
+        where I: IntoIterator<Item = MapPoint>,
+    {
+        for point in points {
+            let ent = match self.map.get(&point).and_then(|tile| tile.entity) {
+                Some(ent) => ent,
+                None => continue,
+            };
+            if let Some(sprite) = rules.sprite_at(&self.map, &self.legend, &point) {
+                sprites.insert(ent, SpriteComp::new(sprite));
+            }
+        }
+    }
+
+    /// The highest platform, slope or ladder top in pixel column `x` between pixel
+    /// rows `top` and `bottom`, as a pixel row. Only tile types `accept` picks
+    /// are considered.
+    pub fn surface_between<F>(&self, x: f32, top: f32, bottom: f32, accept: F) -> Option<f32>
+        where F: Fn(&TileType) -> bool,
+    {
+        if x < 0.0 || bottom < top {
+            return None;
+        }
+
+        let column = x as i32 / TILE_WIDTH;
+        let local_x = x - (column * TILE_WIDTH) as f32;
+        let first_row = top.max(0.0) as i32 / TILE_HEIGHT;
+        let last_row = bottom.max(0.0) as i32 / TILE_HEIGHT;
+        for row in first_row..=last_row {
+            let tile = match self.map.get(&MapPoint::new(column, row, 0)) {
+                Some(tile) => tile,
+                None => continue,
+            };
+            let tile_type = match tile.tile_type {
+                Some(tile_type) if accept(&tile_type) => tile_type,
+                _ => continue,
+            };
+            // Only the top of a ladder or vine can be stood on
+            let offset = if tile_type.is_climbable() {
+                let above = MapPoint::new(column, row - 1, 0);
+                if self.is_climbable(&above) { None } else { Some(0.0) }
+            } else {
+                tile.surface_at(local_x)
+            };
+            let offset = match offset {
+                Some(offset) => offset,
+                None => continue,
+            };
+
+            let y = (row * TILE_HEIGHT) as f32 + offset;
+            if y >= top && y <= bottom {
+                return Some(y);
+            }
+        }
+        None
+    }
+
+    pub fn is_climbable(&self, point: &MapPoint) -> bool {
+        self.map.get(point).and_then(|tile| tile.tile_type).map_or(false, |t| t.is_climbable())
     }
 
     pub fn has_ground_at(&self, point: &MapPoint) -> bool {
@@ -80,7 +476,7 @@ impl SkirmMap {
         let points = line_drawing::Bresenham::new(p1.as_tuple(), p2.as_tuple());
         let mut vec = Vec::new();
         for (x, y) in points {
-            vec.push(MapPoint::new(x, y));
+            vec.push(MapPoint::new(x, y, p1.z));
         }
         vec
     }
@@ -92,15 +488,28 @@ impl SkirmMap {
         }
     }
 
+    /// Solid tiles and anything off the map block sight.
+    pub fn is_opaque(&self, point: &MapPoint) -> bool {
+        self.map.get(point).map_or(true, |tile| tile.solid)
+    }
+
     pub fn has_line_of_sight(&self, p1: &MapPoint, p2: &MapPoint) -> bool {
-        let tiles_to_check = self.get_tiles_between(p1, p2);
-        let mut has_sight = true;
-        for tile in tiles_to_check {
-            if !self.has_ground_at(&tile) {
-                has_sight = false;
-            }
-        }
-        has_sight
+        line_drawing::Bresenham::new(p1.as_tuple(), p2.as_tuple())
+            .map(|(x, y)| MapPoint::new(x, y, p1.z))
+            .all(|point| point == *p2 || !self.is_opaque(&point))
+    }
+
+    /// Every tile visible from `origin` within `radius` tiles.
+    pub fn field_of_view(&self, origin: &MapPoint, radius: i32) -> HashSet<MapPoint> {
+        fov::field_of_view(self, origin, radius)
+    }
+
+    /// The z-level of the tile at `x`, `y` whose sprite entity is `ent`, if
+    /// there is one. Every z-level's tiles are drawn at the same pixels.
+    pub fn tile_entity_level(&self, ent: Entity, x: i32, y: i32) -> Option<i32> {
+        (0..self.levels()).find(|z| {
+            self.map.get(&MapPoint::new(x, y, *z)).map_or(false, |tile| tile.entity == Some(ent))
+        })
     }
 
     pub fn add_occupant(&mut self, ent: Entity, point: MapPoint) -> Result<(), MapError> {
@@ -111,6 +520,7 @@ impl SkirmMap {
             result = match self.map.get_mut(&point) {
                 Some(tile) => {
                     tile.occupant = Some(ent);
+                    self.overlapping.entry(point).or_insert_with(Vec::new).push(ent);
                     Ok(())
                 },
                 None => Err(MapError::PointDoesNotExist)
@@ -130,7 +540,71 @@ impl SkirmMap {
     }
 
     pub fn get_occupant(&self, point: &MapPoint) -> Option<Entity> {
-        Some(self.map.get(point).unwrap().occupant.unwrap())
+        self.map.get(point).and_then(|tile| tile.occupant)
+    }
+
+    /// Every entity overlapping the tile at `point`, occupant or not.
+    pub fn entities_at(&self, point: &MapPoint) -> &[Entity] {
+        self.overlapping.get(point).map_or(&[], |ents| ents.as_slice())
+    }
+
+    /// Forgets every occupant, ready for them all to be placed again.
+    pub fn clear_occupants(&mut self) {
+        for point in self.overlapping.keys() {
+            if let Some(tile) = self.map.get_mut(point) {
+                tile.occupant = None;
+            }
+        }
+        self.overlapping.clear();
+    }
+
+    /// Places an entity whose sprite's top-left corner is at pixel (`x`, `y`).
+    /// It occupies the tile under its center, unless something got there
+    /// first, and overlaps every tile its sprite touches.
+    pub fn occupy(&mut self, ent: Entity, x: f32, y: f32) {
+        let (x, y) = (x as i32, y as i32);
+        let center = MapPoint::from_pixel_coord(x + TILE_WIDTH / 2, y + TILE_HEIGHT / 2, 0);
+        if let Some(tile) = self.map.get_mut(&center) {
+            if tile.occupant.is_none() {
+                tile.occupant = Some(ent);
+            }
+        }
+
+        let min = MapPoint::from_pixel_coord(x, y, 0);
+        let max = MapPoint::from_pixel_coord(x + TILE_WIDTH - 1, y + TILE_HEIGHT - 1, 0);
+        for j in min.y..=max.y {
+            for i in min.x..=max.x {
+                let point = MapPoint::new(i, j, 0);
+                if self.in_bounds(&point) {
+                    self.overlapping.entry(point).or_insert_with(Vec::new).push(ent);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl SkirmMap {
+    /// A map of one z-level drawn with the shared legend's glyphs, without
+    /// any entities or colliders, for tests that only look at its tiles.
+    pub fn from_rows(rows: &[&str]) -> Self {
+        let legend = Legend::load(DEFAULT_LEGEND_PATH).expect("The shared legend loads");
+        let levels = [rows.iter().map(|row| row.chars().collect()).collect::<Vec<Vec<char>>>()];
+        let mut meta = MapMeta::new(String::from("Test"));
+        meta.add_markers(&levels, &legend);
+
+        Self {
+            map: TileGrid::from_glyphs(&levels, &legend),
+            legend,
+            spawn: meta.player_spawns.first().cloned().unwrap_or(MapPoint::new(0, 0, 0)),
+            elevator: meta.elevator,
+            current_z: 0,
+            meta,
+            source: None,
+            colliders: TileColliders::new(),
+            secret_linger: HashMap::new(),
+            overlapping: HashMap::new(),
+        }
     }
 }
 
@@ -142,40 +616,323 @@ pub fn pixel_distance(p1: (i32, i32), p2: (i32, i32)) -> u16 {
     (((p1.0 - p2.0).pow(2) + (p1.1 - p2.1).pow(2)) as f32).sqrt() as u16
 }
 
-fn create_map_entity(world: &mut specs::World, map: &mut HashMap<MapPoint, Tile>, x: i32, y: i32, tile: Option<TileType>) {
-    // Insert into the map for quick lookup
-    let point = MapPoint::new(x, y);
-    map.insert(point, Tile::new(tile));
+fn level_path(dir: &Path, z: i32) -> PathBuf {
+    dir.join(format!("{}.txt", z))
+}
+
+/// Reads one z-level into rows of glyphs, failing on the first glyph that the
+/// legend doesn't know about.
+fn read_level(path: &Path, legend: &Legend) -> Result<Vec<Vec<char>>, MapError> {
+    let io_error = |e: std::io::Error| MapError::Io(path.to_path_buf(), e.to_string());
+    let level_file = File::open(path).map_err(io_error)?;
+    let buffer = BufReader::new(level_file);
+
+    let mut rows = Vec::new();
+    for (j, line) in buffer.lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        let mut row = Vec::new();
+        for (i, c) in line.chars().enumerate() {
+            let location = FileLocation::new(path.to_path_buf(), j + 1, i + 1);
+            let glyph = match c.to_ascii_char() {
+                Ok(glyph) => glyph.as_char(),
+                Err(_) => return Err(MapError::NonAsciiGlyph(location, c)),
+            };
+            if legend.get(glyph).is_none() {
+                return Err(MapError::UnknownGlyph(location, glyph));
+            }
+            row.push(glyph);
+        }
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Every z-level has to be a `width` by `height` rectangle, like "0.txt".
+fn check_level_size(path: &Path, width: usize, height: usize, level: &[Vec<char>]) -> Result<(), MapError> {
+    let ragged = |line: usize, column: usize| {
+        Err(MapError::RaggedLevel(FileLocation::new(path.to_path_buf(), line, column)))
+    };
+
+    for (j, row) in level.iter().enumerate() {
+        if row.len() != width {
+            return ragged(j + 1, row.len().min(width) + 1);
+        }
+    }
+
+    if level.len() != height {
+        return ragged(level.len().min(height) + 1, 1);
+    }
+
+    Ok(())
+}
+
+/// The first tile, in z, row and column order, whose legend entry matches.
+/// Makes sure everything the metadata places is on the map and out of the
+/// walls, so nothing gets created inside solid rock or off the edge.
+fn check_meta_points(path: &Path, meta: &MapMeta, levels: &[Vec<Vec<char>>], legend: &Legend) -> Result<(), MapError> {
+    let mut points = Vec::new();
+    points.extend(meta.player_spawns.iter().map(|point| ("player spawn", *point)));
+    points.extend(meta.enemy_spawners.iter().map(|spawner| ("enemy spawner", spawner.point)));
+    points.extend(meta.items.iter().map(|pickup| ("item", pickup.point)));
+    points.extend(meta.elevator.iter().map(|point| ("elevator", *point)));
+
+    for (what, point) in points {
+        let glyph = levels.get(point.z as usize)
+            .and_then(|level| level.get(point.y as usize))
+            .and_then(|row| row.get(point.x as usize));
+        let glyph = match glyph {
+            Some(glyph) if point.x >= 0 && point.y >= 0 && point.z >= 0 => *glyph,
+            _ => return Err(MapError::BadMetaPoint(path.to_path_buf(), what, point, "off the map")),
+        };
+        if legend.get(glyph).map_or(false, |entry| entry.solid) {
+            return Err(MapError::BadMetaPoint(path.to_path_buf(), what, point, "inside a solid tile"));
+        }
+    }
+    Ok(())
+}
 
-    if tile.is_some() {
+fn find_marker<F>(levels: &[Vec<Vec<char>>], legend: &Legend, is_marker: F) -> Option<MapPoint>
+    where F: Fn(&LegendEntry) -> bool,
+{
+    for (z, level) in levels.iter().enumerate() {
+        for (j, row) in level.iter().enumerate() {
+            for (i, glyph) in row.iter().enumerate() {
+                if legend.get(*glyph).map_or(false, |entry| is_marker(entry)) {
+                    return Some(MapPoint::new(i as i32, j as i32, z as i32));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn create_tile_entity(world: &mut specs::World, point: &MapPoint, sprite: Option<String>) -> Option<Entity> {
+    sprite.map(|sprite| {
         let pixel = point.as_float_coord_tuple();
-        let ent = world.create_entity()
+        world.create_entity()
             .with(PositionComp::new(pixel.0, pixel.1))
-            .with(SpriteComp::new(String::from("green_box")))
-            .build();
-
-        // Collision info
-        let shape = Cuboid::new(Vector2::new(12.0, 12.0));
-        let mut group = CollisionGroups::new();
-        group.set_membership(&[TILE_COLLISION_GROUP]);
-        group.set_blacklist(&[TILE_COLLISION_GROUP]);
-        let query_type = GeometricQueryType::Contacts(0.0, 0.0);
-
-        let collider = {
-            let mut collide_world = world.write_resource::<CollisionWorld>();
-            let handle = collide_world.add(
-                Isometry2::new(Vector2::new(0.0, -6.0), nalgebra::zero()),
-                ShapeHandle::new(shape.clone()),
-                group,
-                query_type,
-                ent,
-            );
-
-            CollideComp {
-                handle,
+            .with(SpriteComp::new(sprite))
+            .build()
+    })
+}
+
+/// Brings a tile's sprite entity in line with its new legend entry, creating or
+/// deleting the entity as needed.
+fn update_tile_entity(point: &MapPoint, ent: Option<Entity>, entry: &LegendEntry, access: &mut TerrainAccess) -> Option<Entity> {
+    match (ent, &entry.sprite) {
+        (Some(ent), Some(sprite)) => {
+            access.sprites.insert(ent, SpriteComp::new(sprite.clone()));
+            Some(ent)
+        },
+        (Some(ent), None) => {
+            if let Err(e) = access.entities.delete(ent) {
+                warn!("Tile entity was already deleted, {:?}", e);
             }
-        };
+            None
+        },
+        (None, Some(sprite)) => {
+            let pixel = point.as_float_coord_tuple();
+            let ent = access.entities.create();
+            access.positions.insert(ent, PositionComp::new(pixel.0, pixel.1));
+            access.sprites.insert(ent, SpriteComp::new(sprite.clone()));
+            Some(ent)
+        },
+        (None, None) => None,
+    }
+}
+
+/// Adds merged colliders for the solid tiles of `map`. The collision world is
+/// flat, so only the bottom z-level collides.
+fn create_map_colliders(world: &mut specs::World, map: &TileGrid) -> TileColliders {
+    let solid: HashSet<(i32, i32)> = map.iter()
+        .filter(|(point, tile)| point.z == 0 && tile.solid)
+        .map(|(point, _)| (point.x, point.y))
+        .collect();
+
+    let mut colliders = TileColliders::new();
+    colliders.add_tiles(
+        &solid,
+        0,
+        &world.entities(),
+        &mut world.write::<CollideComp>(),
+        &mut world.write_resource::<CollisionWorld>(),
+    );
+    info!("Merged {} solid tiles into {} colliders", solid.len(), colliders.len());
+
+    colliders
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::World;
+
+    use super::*;
+
+    const TEST_MAP: &str = "./resources/maps/test";
+
+    fn world() -> World {
+        let mut world = World::new();
+        register_components(&mut world);
+        world.add_resource(CollisionWorld::new(0.02));
+        world.add_resource(AutotileRules::default());
+        world
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).expect("Old temp dir is removed");
+        }
+        dir
+    }
+
+    /// Everything about each tile that's saved, leaving out its entities.
+    fn tiles(map: &SkirmMap) -> Vec<(MapPoint, char, Option<TileType>, bool)> {
+        map.map.iter().map(|(point, tile)| (point, tile.glyph, tile.tile_type, tile.solid)).collect()
+    }
+
+    /// Runs `f` with terrain access to `world`, handing back the events raised.
+    fn with_access<F>(world: &World, f: F) -> TerrainEvents
+        where F: FnOnce(&mut TerrainAccess),
+    {
+        let entities = world.entities();
+        let mut positions = world.write::<PositionComp>();
+        let mut sprites = world.write::<SpriteComp>();
+        let mut collide = world.write::<CollideComp>();
+        let mut collide_world = world.write_resource::<CollisionWorld>();
+        let mut events = TerrainEvents::new();
+        f(&mut TerrainAccess {
+            entities: &entities,
+            positions: &mut positions,
+            sprites: &mut sprites,
+            collide: &mut collide,
+            collide_world: &mut *collide_world,
+            events: &mut events,
+        });
+        events
+    }
+
+    /// Digs out a wall, places a platform and opens the secret wall of the
+    /// test map.
+    fn edit(map: &mut SkirmMap, world: &World) {
+        let events = with_access(world, |access| {
+            map.dig(&MapPoint::new(4, 6, 0), access).expect("Wall is dug out");
+            map.place(&MapPoint::new(10, 10, 0), '=', access).expect("Platform is placed");
+            map.reveal(&MapPoint::new(17, 21, 0), access).expect("Secret wall is revealed");
+        });
+        assert_eq!(events.events.len(), 3);
+    }
+
+    #[test]
+    fn saved_maps_load_back_the_same() {
+        let mut world = world();
+        let mut map = SkirmMap::load(TEST_MAP, &mut world).expect("Test map loads");
+        edit(&mut map, &world);
+
+        let dir = temp_dir("aok_map_round_trip");
+        map.save(&dir).expect("Map saves");
+        let reloaded = SkirmMap::load(&dir, &mut world).expect("Saved map loads");
+
+        assert_eq!(tiles(&reloaded), tiles(&map));
+        assert_eq!(reloaded.map.get(&MapPoint::new(4, 6, 0)).map(|tile| tile.glyph), Some(DUG_GLYPH));
+        assert_eq!(reloaded.map.get(&MapPoint::new(10, 10, 0)).map(|tile| tile.glyph), Some('='));
+        assert_eq!(reloaded.map.get(&MapPoint::new(17, 21, 0)).map(|tile| tile.glyph), Some(DUG_GLYPH));
+        assert_eq!(reloaded.meta, map.meta);
+        assert_eq!(reloaded.spawn, map.spawn);
+        assert_eq!(reloaded.elevator, map.elevator);
+        assert_eq!(reloaded.legend, map.legend);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn walls_are_not_placed_over_overlapping_entities() {
+        let mut world = world();
+        let mut map = SkirmMap::load(TEST_MAP, &mut world).expect("Test map loads");
+        let ent = world.create_entity().build();
+        let point = MapPoint::new(10, 10, 0);
+
+        // Centered on the tile to the left, hanging over into this one
+        let x = (point.x * TILE_WIDTH - TILE_WIDTH / 2 - 1) as f32;
+        map.occupy(ent, x, (point.y * TILE_HEIGHT) as f32);
+        assert!(!map.has_occupant(&point));
+        assert_eq!(map.entities_at(&point), &[ent]);
+
+        with_access(&world, |access| {
+            assert_eq!(map.place(&point, '#', access), Err(MapError::Occupied));
+            assert_eq!(map.place(&point, '=', access), Ok(()));
+        });
+    }
+
+    #[test]
+    fn saving_keeps_every_marker_glyph() {
+        let source = temp_dir("aok_map_markers_source");
+        fs::create_dir_all(&source).expect("Temp map dir is created");
+        fs::write(level_path(&source, 0), "#######\n#@...@#\n#.....#\n#######\n").expect("Level is written");
+        fs::write(source.join(META_FILE), "(player_spawns: [(x: 3, y: 2, z: 0)])").expect("Metadata is written");
+
+        let mut world = world();
+        let map = SkirmMap::load(&source, &mut world).expect("Temp map loads");
+        assert_eq!(map.spawn, MapPoint::new(3, 2, 0));
+
+        let dir = temp_dir("aok_map_markers_saved");
+        map.save(&dir).expect("Map saves");
+        let reloaded = SkirmMap::load(&dir, &mut world).expect("Saved map loads");
+
+        assert_eq!(tiles(&reloaded), tiles(&map));
+        assert_eq!(reloaded.map.get(&MapPoint::new(3, 2, 0)).map(|tile| tile.glyph), Some('.'));
+        assert_eq!(reloaded.map.get(&MapPoint::new(1, 1, 0)).map(|tile| tile.glyph), Some('@'));
+        assert_eq!(reloaded.map.get(&MapPoint::new(5, 1, 0)).map(|tile| tile.glyph), Some('@'));
+        assert_eq!(reloaded.meta, map.meta);
+        assert_eq!(reloaded.spawn, map.spawn);
+
+        fs::remove_dir_all(&source).ok();
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn meta_points_off_the_map_or_in_walls_are_errors() {
+        let dir = temp_dir("aok_map_bad_meta");
+        fs::create_dir_all(&dir).expect("Temp map dir is created");
+        fs::write(level_path(&dir, 0), "#####\n#@..#\n#####\n").expect("Level is written");
+        let meta_path = dir.join(META_FILE);
+        let mut world = world();
+
+        fs::write(&meta_path, "(player_spawns: [(x: 9, y: 1, z: 0)])").expect("Metadata is written");
+        let err = SkirmMap::load(&dir, &mut world).err();
+        assert_eq!(err, Some(MapError::BadMetaPoint(meta_path.clone(), "player spawn", MapPoint::new(9, 1, 0), "off the map")));
+
+        fs::write(&meta_path, "(items: [(item: \".22 Rifle\", point: (x: 2, y: 0, z: 0))])").expect("Metadata is written");
+        let err = SkirmMap::load(&dir, &mut world).err();
+        assert_eq!(err, Some(MapError::BadMetaPoint(meta_path.clone(), "item", MapPoint::new(2, 0, 0), "inside a solid tile")));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn saving_again_writes_the_same_bytes() {
+        let mut world = world();
+        let mut map = SkirmMap::load(TEST_MAP, &mut world).expect("Test map loads");
+        edit(&mut map, &world);
+
+        let first_dir = temp_dir("aok_map_save_first");
+        let second_dir = temp_dir("aok_map_save_second");
+        map.save(&first_dir).expect("Map saves");
+        let reloaded = SkirmMap::load(&first_dir, &mut world).expect("Saved map loads");
+        reloaded.save(&second_dir).expect("Reloaded map saves");
+
+        for z in 0..map.levels() {
+            assert_eq!(reloaded.level_text(z), map.level_text(z));
+            let first = fs::read(level_path(&first_dir, z)).expect("First save is readable");
+            let second = fs::read(level_path(&second_dir, z)).expect("Second save is readable");
+            assert_eq!(first, second);
+        }
+        let first_meta = fs::read(first_dir.join(META_FILE)).expect("First metadata is readable");
+        let second_meta = fs::read(second_dir.join(META_FILE)).expect("Second metadata is readable");
+        assert_eq!(first_meta, second_meta);
 
-        world.write::<CollideComp>().insert(ent, collider);
+        fs::remove_dir_all(&first_dir).ok();
+        fs::remove_dir_all(&second_dir).ok();
     }
 }
@@ -0,0 +1,96 @@
+use ron;
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    path::Path,
+};
+
+use crate::map::{Legend, MapError, MapPoint, TileGrid};
+
+pub const DEFAULT_AUTOTILE_PATH: &str = "./resources/maps/autotile.ron";
+
+// Clockwise from north, with the corners in between the edges
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (0, -1), (1, -1), (1, 0), (1, 1),
+    (0, 1), (-1, 1), (-1, 0), (-1, -1),
+];
+
+/// Which neighbours make up a tile's bitmask.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum Neighbourhood {
+    // North 1, east 2, south 4, west 8
+    Four,
+    // Clockwise from north 1 to north-west 128. A corner only counts when both
+    // edges next to it connect as well, which leaves 47 distinct masks.
+    Eight,
+}
+
+impl Neighbourhood {
+    fn mask<F>(self, connects: F) -> u8
+        where F: Fn(i32, i32) -> bool,
+    {
+        let open: Vec<bool> = NEIGHBOURS.iter().map(|(dx, dy)| connects(*dx, *dy)).collect();
+        match self {
+            Neighbourhood::Four => (0..4)
+                .filter(|edge| open[edge * 2])
+                .fold(0, |mask, edge| mask | 1 << edge),
+            Neighbourhood::Eight => (0..8)
+                .filter(|&bit| open[bit] && (bit % 2 == 0 || (open[bit - 1] && open[(bit + 1) % 8])))
+                .fold(0, |mask, bit| mask | 1 << bit),
+        }
+    }
+}
+
+/// The sprites a legend sprite is drawn with, picked by the bitmask of which
+/// neighbours share it. Masks without a sprite here keep the legend's.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Tileset {
+    pub neighbours: Neighbourhood,
+    pub sprites: HashMap<u8, String>,
+}
+
+/// Rules for choosing tile sprites from their neighbours, keyed by the
+/// legend sprite they replace. Tiles with any other sprite are left alone.
+#[derive(Clone, Debug, Default)]
+pub struct AutotileRules {
+    tilesets: HashMap<String, Tileset>,
+}
+
+impl AutotileRules {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MapError> {
+        let path = path.as_ref();
+        let io_error = |e: std::io::Error| MapError::Io(path.to_path_buf(), e.to_string());
+
+        let mut rules_file = File::open(path).map_err(io_error)?;
+        let mut buffer = String::new();
+        rules_file.read_to_string(&mut buffer).map_err(io_error)?;
+        let tilesets = ron::de::from_str(buffer.as_str())
+            .map_err(|e| MapError::BadAutotile(path.to_path_buf(), format!("{:?}", e)))?;
+
+        Ok(Self { tilesets })
+    }
+
+    /// The sprite the tile at `point` is drawn with, if any. Neighbours off
+    /// the edge of the map count as connected, so walls run on past it.
+    pub fn sprite_at(&self, map: &TileGrid, legend: &Legend, point: &MapPoint) -> Option<String> {
+        let sprite = legend_sprite(map, legend, point)?;
+        let tileset = match self.tilesets.get(sprite) {
+            Some(tileset) => tileset,
+            None => return Some(sprite.clone()),
+        };
+
+        let mask = tileset.neighbours.mask(|dx, dy| {
+            let neighbour = point.offset(dx, dy);
+            !map.in_bounds(&neighbour) || legend_sprite(map, legend, &neighbour) == Some(sprite)
+        });
+        Some(tileset.sprites.get(&mask).unwrap_or(sprite).clone())
+    }
+}
+
+fn legend_sprite<'l>(map: &TileGrid, legend: &'l Legend, point: &MapPoint) -> Option<&'l String> {
+    map.get(point)
+        .and_then(|tile| legend.get(tile.glyph))
+        .and_then(|entry| entry.sprite.as_ref())
+}
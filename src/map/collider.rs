@@ -0,0 +1,370 @@
+use ncollide2d::{
+    shape::{Cuboid, ShapeHandle},
+    world::{CollisionGroups, CollisionObjectHandle, GeometricQueryType},
+};
+
+use nalgebra::Isometry2;
+use specs::{Entity, Entities, WriteStorage};
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    game::TILE_COLLISION_GROUP,
+    CollisionWorld,
+    Vector2,
+    components::CollideComp,
+    map::{MapPoint, TILE_WIDTH, TILE_HEIGHT},
+};
+
+/// A rectangle of tiles on one z-level, in tile coordinates.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct TileRect {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl TileRect {
+    pub fn points(&self) -> Vec<MapPoint> {
+        let mut points = Vec::new();
+        for y in self.y..self.y + self.height {
+            for x in self.x..self.x + self.width {
+                points.push(MapPoint::new(x, y, self.z));
+            }
+        }
+        points
+    }
+
+    fn pixel_half_extents(&self) -> Vector2 {
+        Vector2::new(
+            (self.width * TILE_WIDTH) as f32 / 2.0,
+            (self.height * TILE_HEIGHT) as f32 / 2.0,
+        )
+    }
+
+    fn pixel_center(&self) -> Vector2 {
+        let (x, y) = MapPoint::new(self.x, self.y, self.z).as_float_coord_tuple();
+        Vector2::new(x, y) + self.pixel_half_extents()
+    }
+}
+
+/// One merged collider and the entity that owns it in the `CollisionWorld`.
+#[derive(Debug, Clone, Copy)]
+pub struct TileCollider {
+    pub rect: TileRect,
+    pub ent: Entity,
+    pub handle: CollisionObjectHandle,
+}
+
+/// Solid tiles merged into as few cuboids as possible, with a lookup from each
+/// tile back to the collider covering it.
+#[derive(Debug, Default)]
+pub struct TileColliders {
+    colliders: HashMap<usize, TileCollider>,
+    by_tile: HashMap<MapPoint, usize>,
+    next_id: usize,
+}
+
+impl TileColliders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.colliders.len()
+    }
+
+    pub fn get(&self, point: &MapPoint) -> Option<&TileCollider> {
+        self.by_tile.get(point).and_then(|id| self.colliders.get(id))
+    }
+
+    /// Merges `solid` tiles on z-level `z` and adds a collider for each rectangle.
+    pub fn add_tiles<'a>(
+        &mut self,
+        solid: &HashSet<(i32, i32)>,
+        z: i32,
+        entities: &Entities<'a>,
+        collide: &mut WriteStorage<'a, CollideComp>,
+        collide_world: &mut CollisionWorld,
+    ) {
+        for rect in merge_tiles(solid, z) {
+            self.add_rect(rect, entities, collide, collide_world);
+        }
+    }
+
+    /// Adds a collider for a newly solid tile, re-merging it with the
+    /// colliders it touches.
+    pub fn add_tile<'a>(
+        &mut self,
+        point: &MapPoint,
+        entities: &Entities<'a>,
+        collide: &mut WriteStorage<'a, CollideComp>,
+        collide_world: &mut CollisionWorld,
+    ) {
+        if self.by_tile.contains_key(point) {
+            return;
+        }
+
+        let mut touching: Vec<usize> = [(0, -1), (-1, 0), (1, 0), (0, 1)].iter()
+            .filter_map(|&(x, y)| self.by_tile.get(&MapPoint::new(point.x + x, point.y + y, point.z)))
+            .cloned()
+            .collect();
+        touching.sort();
+        touching.dedup();
+
+        let mut solid = self.take(&touching, entities, collide, collide_world);
+        solid.insert((point.x, point.y));
+        self.add_tiles(&solid, point.z, entities, collide, collide_world);
+    }
+
+    /// Removes a tile from its collider, re-merging whatever is left of it.
+    pub fn remove_tile<'a>(
+        &mut self,
+        point: &MapPoint,
+        entities: &Entities<'a>,
+        collide: &mut WriteStorage<'a, CollideComp>,
+        collide_world: &mut CollisionWorld,
+    ) {
+        let id = match self.by_tile.get(point) {
+            Some(id) => *id,
+            None => return,
+        };
+
+        let mut solid = self.take(&[id], entities, collide, collide_world);
+        solid.remove(&(point.x, point.y));
+        self.add_tiles(&solid, point.z, entities, collide, collide_world);
+    }
+
+    /// Removes every collider from the world.
+    pub fn clear<'a>(
+        &mut self,
+        entities: &Entities<'a>,
+        collide: &mut WriteStorage<'a, CollideComp>,
+        collide_world: &mut CollisionWorld,
+    ) {
+        let ids: Vec<usize> = self.colliders.keys().cloned().collect();
+        self.take(&ids, entities, collide, collide_world);
+    }
+
+    /// Removes colliders from the world, handing back the tiles they covered.
+    fn take<'a>(
+        &mut self,
+        ids: &[usize],
+        entities: &Entities<'a>,
+        collide: &mut WriteStorage<'a, CollideComp>,
+        collide_world: &mut CollisionWorld,
+    ) -> HashSet<(i32, i32)> {
+        let mut solid = HashSet::new();
+        for id in ids {
+            if let Some(collider) = self.colliders.remove(id) {
+                collide_world.remove(&[collider.handle]);
+                collide.remove(collider.ent);
+                if let Err(e) = entities.delete(collider.ent) {
+                    warn!("Tile collider entity was already deleted, {:?}", e);
+                }
+
+                for point in collider.rect.points() {
+                    self.by_tile.remove(&point);
+                    solid.insert((point.x, point.y));
+                }
+            }
+        }
+        solid
+    }
+
+    fn add_rect<'a>(
+        &mut self,
+        rect: TileRect,
+        entities: &Entities<'a>,
+        collide: &mut WriteStorage<'a, CollideComp>,
+        collide_world: &mut CollisionWorld,
+    ) {
+        let ent = entities.create();
+
+        // Collision info
+        let shape = Cuboid::new(rect.pixel_half_extents());
+        let mut group = CollisionGroups::new();
+        group.set_membership(&[TILE_COLLISION_GROUP]);
+        group.set_blacklist(&[TILE_COLLISION_GROUP]);
+        let query_type = GeometricQueryType::Contacts(0.0, 0.0);
+
+        let handle = collide_world.add(
+            Isometry2::new(rect.pixel_center(), nalgebra::zero()),
+            ShapeHandle::new(shape),
+            group,
+            query_type,
+            ent,
+        );
+        collide.insert(ent, CollideComp { handle });
+
+        let id = self.next_id;
+        self.next_id += 1;
+        for point in rect.points() {
+            self.by_tile.insert(point, id);
+        }
+        self.colliders.insert(id, TileCollider { rect, ent, handle });
+    }
+}
+
+/// Greedily merges tiles into rectangles: each unmerged tile, in row order,
+/// grows right as far as it can and then down while the whole run is solid.
+pub fn merge_tiles(solid: &HashSet<(i32, i32)>, z: i32) -> Vec<TileRect> {
+    let mut points: Vec<(i32, i32)> = solid.iter().cloned().collect();
+    points.sort_by_key(|&(x, y)| (y, x));
+
+    let mut merged = HashSet::new();
+    let mut rects = Vec::new();
+    for (x, y) in points {
+        if merged.contains(&(x, y)) {
+            continue;
+        }
+
+        let free = |px: i32, py: i32| solid.contains(&(px, py)) && !merged.contains(&(px, py));
+        let mut width = 1;
+        while free(x + width, y) {
+            width += 1;
+        }
+        let mut height = 1;
+        while (x..x + width).all(|px| free(px, y + height)) {
+            height += 1;
+        }
+
+        let rect = TileRect { x, y, z, width, height };
+        for point in rect.points() {
+            merged.insert((point.x, point.y));
+        }
+        rects.push(rect);
+    }
+
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::World;
+
+    use super::*;
+
+    /// The `#` tiles of a small drawn level.
+    fn solid_tiles(rows: &[&str]) -> HashSet<(i32, i32)> {
+        let mut solid = HashSet::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, glyph) in row.chars().enumerate() {
+                if glyph == '#' {
+                    solid.insert((x as i32, y as i32));
+                }
+            }
+        }
+        solid
+    }
+
+    /// Every tile the rects cover, once for each rect covering it.
+    fn covered(rects: &[TileRect]) -> Vec<(i32, i32)> {
+        rects.iter()
+            .flat_map(|rect| rect.points())
+            .map(|point| (point.x, point.y))
+            .collect()
+    }
+
+    fn assert_exact_cover(rects: &[TileRect], solid: &HashSet<(i32, i32)>) {
+        let covered = covered(rects);
+        assert_eq!(covered.len(), solid.len(), "Rects overlap or cover too much");
+        assert_eq!(covered.into_iter().collect::<HashSet<_>>(), *solid);
+    }
+
+    fn collision_world() -> World {
+        let mut world = World::new();
+        world.register::<CollideComp>();
+        world.add_resource(CollisionWorld::new(0.02));
+        world
+    }
+
+    fn colliders_from(world: &World, solid: &HashSet<(i32, i32)>) -> TileColliders {
+        let mut colliders = TileColliders::new();
+        colliders.add_tiles(solid, 0, &world.entities(), &mut world.write::<CollideComp>(), &mut world.write_resource::<CollisionWorld>());
+        colliders
+    }
+
+    fn rects(colliders: &TileColliders) -> Vec<TileRect> {
+        colliders.colliders.values().map(|collider| collider.rect).collect()
+    }
+
+    #[test]
+    fn merged_rects_cover_exactly_the_solid_tiles() {
+        let solid = solid_tiles(&[
+            "##########",
+            "#........#",
+            "#.####...#",
+            "#.#..#..##",
+            "#....#..##",
+            "##########",
+        ]);
+        assert_exact_cover(&merge_tiles(&solid, 0), &solid);
+    }
+
+    #[test]
+    fn solid_block_merges_into_one_rect() {
+        let solid = solid_tiles(&[
+            "...",
+            ".##",
+            ".##",
+        ]);
+        assert_eq!(merge_tiles(&solid, 2), vec![TileRect { x: 1, y: 1, z: 2, width: 2, height: 2 }]);
+    }
+
+    #[test]
+    fn removing_a_tile_splits_its_collider() {
+        let world = collision_world();
+        let solid = solid_tiles(&["#####"]);
+        let mut colliders = colliders_from(&world, &solid);
+        assert_eq!(colliders.len(), 1);
+
+        let point = MapPoint::new(2, 0, 0);
+        colliders.remove_tile(&point, &world.entities(), &mut world.write::<CollideComp>(), &mut world.write_resource::<CollisionWorld>());
+
+        let mut expected = solid.clone();
+        expected.remove(&(2, 0));
+        assert_eq!(colliders.len(), 2);
+        assert!(colliders.get(&point).is_none());
+        assert_exact_cover(&rects(&colliders), &expected);
+        assert_eq!(world.read_resource::<CollisionWorld>().collision_objects().count(), 2);
+    }
+
+    #[test]
+    fn adding_a_tile_merges_with_its_neighbours() {
+        let world = collision_world();
+        let solid = solid_tiles(&["##.##"]);
+        let mut colliders = colliders_from(&world, &solid);
+        assert_eq!(colliders.len(), 2);
+
+        let point = MapPoint::new(2, 0, 0);
+        colliders.add_tile(&point, &world.entities(), &mut world.write::<CollideComp>(), &mut world.write_resource::<CollisionWorld>());
+
+        assert_eq!(colliders.len(), 1);
+        assert_eq!(colliders.get(&point).map(|collider| collider.rect), Some(TileRect { x: 0, y: 0, z: 0, width: 5, height: 1 }));
+        assert_exact_cover(&rects(&colliders), &solid_tiles(&["#####"]));
+        assert_eq!(world.read_resource::<CollisionWorld>().collision_objects().count(), 1);
+    }
+
+    #[test]
+    fn adding_a_tile_below_keeps_the_cover_exact() {
+        let world = collision_world();
+        let solid = solid_tiles(&[
+            "###",
+            "...",
+        ]);
+        let mut colliders = colliders_from(&world, &solid);
+
+        colliders.add_tile(&MapPoint::new(1, 1, 0), &world.entities(), &mut world.write::<CollideComp>(), &mut world.write_resource::<CollisionWorld>());
+
+        assert_exact_cover(&rects(&colliders), &solid_tiles(&[
+            "###",
+            ".#.",
+        ]));
+        for (x, y) in solid_tiles(&["###", ".#."]) {
+            assert!(colliders.get(&MapPoint::new(x, y, 0)).is_some());
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use ggez::GameError;
+
+use std::{
+    fmt,
+    path::PathBuf,
+};
+
+use crate::map::MapPoint;
+
+/// A 1-based position inside a map or legend file.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct FileLocation {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl FileLocation {
+    pub fn new(file: PathBuf, line: usize, column: usize) -> Self {
+        Self { file, line, column }
+    }
+}
+
+impl fmt::Display for FileLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum MapError {
+    Occupied,
+    PointDoesNotExist,
+    NotSolid,
+    NotSecret,
+    NotInLegend(char),
+    Io(PathBuf, String),
+    BadLegend(PathBuf, String),
+    MissingBaseLevel(PathBuf),
+    NonAsciiGlyph(FileLocation, char),
+    UnknownGlyph(FileLocation, char),
+    RaggedLevel(FileLocation),
+    NoSpawnPoint(PathBuf),
+    BadTiled(PathBuf, String),
+    BadMeta(PathBuf, String),
+    // What the point places, where, and what's wrong with it
+    BadMetaPoint(PathBuf, &'static str, MapPoint, &'static str),
+    BadAutotile(PathBuf, String),
+    MissingGlyph(PathBuf, &'static str),
+    NoRoute(u64),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Occupied => write!(f, "tile is already occupied"),
+            MapError::PointDoesNotExist => write!(f, "point is not on the map"),
+            MapError::NotSolid => write!(f, "tile is not solid"),
+            MapError::NotSecret => write!(f, "tile is not a secret wall"),
+            MapError::NotInLegend(c) => write!(f, "glyph {:?} is not in the legend", c),
+            MapError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            MapError::BadLegend(path, e) => write!(f, "{}: legend is corrupt, {}", path.display(), e),
+            MapError::MissingBaseLevel(path) => write!(f, "{}: missing bottom z-level", path.display()),
+            MapError::NonAsciiGlyph(loc, c) => write!(f, "{}: non-ASCII glyph {:?}", loc, c),
+            MapError::UnknownGlyph(loc, c) => write!(f, "{}: glyph {:?} is not in the legend", loc, c),
+            MapError::RaggedLevel(loc) => write!(f, "{}: level does not match the size of 0.txt", loc),
+            MapError::NoSpawnPoint(path) => write!(f, "{}: map has no spawn point", path.display()),
+            MapError::BadTiled(path, e) => write!(f, "{}: can't import Tiled map, {}", path.display(), e),
+            MapError::BadMeta(path, e) => write!(f, "{}: map metadata is corrupt, {}", path.display(), e),
+            MapError::BadMetaPoint(path, what, point, problem) => {
+                write!(f, "{}: {} at {:?} is {}", path.display(), what, point, problem)
+            },
+            MapError::BadAutotile(path, e) => write!(f, "{}: autotile rules are corrupt, {}", path.display(), e),
+            MapError::MissingGlyph(path, name) => write!(f, "{}: legend has no {} glyph", path.display(), name),
+            MapError::NoRoute(seed) => write!(f, "no level with a route to the elevator found for seed {}", seed),
+        }
+    }
+}
+
+impl From<MapError> for GameError {
+    fn from(e: MapError) -> GameError {
+        GameError::ResourceLoadError(e.to_string())
+    }
+}
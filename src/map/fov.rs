@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+
+use crate::map::{MapPoint, SkirmMap};
+
+/// The four quadrants scanned, each facing away from the origin.
+#[derive(Clone, Copy)]
+enum Quadrant {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Quadrant {
+    /// Turns a (depth, column) pair within the quadrant into an offset from
+    /// the origin.
+    fn offset(self, depth: i32, col: i32) -> (i32, i32) {
+        match self {
+            Quadrant::North => (col, -depth),
+            Quadrant::South => (col, depth),
+            Quadrant::East => (depth, col),
+            Quadrant::West => (-depth, col),
+        }
+    }
+}
+
+/// Symmetric shadowcasting from `origin`, returning every tile on its z-level
+/// that can be seen within `radius` tiles. Opaque tiles are visible themselves
+/// but hide whatever is behind them, and an open tile sees another exactly
+/// when that one sees it back.
+pub fn field_of_view(map: &SkirmMap, origin: &MapPoint, radius: i32) -> HashSet<MapPoint> {
+    let mut visible = HashSet::new();
+    if !map.in_bounds(origin) {
+        return visible;
+    }
+
+    visible.insert(*origin);
+    for quadrant in [Quadrant::North, Quadrant::South, Quadrant::East, Quadrant::West].iter() {
+        let mut caster = Shadowcaster { map, origin: *origin, radius, quadrant: *quadrant, visible };
+        caster.scan(1, Slope::new(-1, 1), Slope::new(1, 1));
+        visible = caster.visible;
+    }
+    visible
+}
+
+/// A slope from the origin as an exact fraction, so tiles right on the edge
+/// of a shadow are treated the same whichever end is looking.
+#[derive(Clone, Copy)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+impl Slope {
+    fn new(num: i32, den: i32) -> Self {
+        Self { num, den }
+    }
+
+    /// The slope through the near corner of the tile at `depth`, `col`.
+    fn of_tile(depth: i32, col: i32) -> Self {
+        Self::new(2 * col - 1, 2 * depth)
+    }
+
+    /// The column the slope passes through at `depth`, rounding halves up.
+    fn first_col(&self, depth: i32) -> i32 {
+        floor_div(2 * depth * self.num + self.den, 2 * self.den)
+    }
+
+    /// The column the slope passes through at `depth`, rounding halves down.
+    fn last_col(&self, depth: i32) -> i32 {
+        -floor_div(self.den - 2 * depth * self.num, 2 * self.den)
+    }
+}
+
+fn floor_div(a: i32, b: i32) -> i32 {
+    let q = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { q - 1 } else { q }
+}
+
+struct Shadowcaster<'m> {
+    map: &'m SkirmMap,
+    origin: MapPoint,
+    radius: i32,
+    quadrant: Quadrant,
+    visible: HashSet<MapPoint>,
+}
+
+impl<'m> Shadowcaster<'m> {
+    fn point(&self, depth: i32, col: i32) -> MapPoint {
+        let (dx, dy) = self.quadrant.offset(depth, col);
+        MapPoint::new(self.origin.x + dx, self.origin.y + dy, self.origin.z)
+    }
+
+    fn reveal(&mut self, depth: i32, col: i32) {
+        let point = self.point(depth, col);
+        if depth * depth + col * col <= self.radius * self.radius && self.map.in_bounds(&point) {
+            self.visible.insert(point);
+        }
+    }
+
+    /// Scans one row of the quadrant between two slopes, then the rows
+    /// behind it through each gap between opaque tiles.
+    fn scan(&mut self, depth: i32, mut start: Slope, end: Slope) {
+        if depth > self.radius {
+            return;
+        }
+
+        let mut prev_opaque = None;
+        for col in start.first_col(depth)..end.last_col(depth) + 1 {
+            let opaque = self.map.is_opaque(&self.point(depth, col));
+            // Open tiles are only seen when their center is in view, which
+            // is what keeps sight symmetric
+            let centered = col * start.den >= depth * start.num && col * end.den <= depth * end.num;
+            if opaque || centered {
+                self.reveal(depth, col);
+            }
+
+            if prev_opaque == Some(true) && !opaque {
+                start = Slope::of_tile(depth, col);
+            }
+            if prev_opaque == Some(false) && opaque {
+                self.scan(depth + 1, start, Slope::of_tile(depth, col));
+            }
+            prev_opaque = Some(opaque);
+        }
+
+        if prev_opaque == Some(false) {
+            self.scan(depth + 1, start, end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_tiles(map: &SkirmMap) -> Vec<MapPoint> {
+        map.map.iter().filter(|(_, tile)| !tile.solid).map(|(point, _)| point).collect()
+    }
+
+    #[test]
+    fn sight_is_symmetric() {
+        let map = SkirmMap::from_rows(&[
+            "############",
+            "#..........#",
+            "#...#......#",
+            "#......##..#",
+            "#.#........#",
+            "#.....#....#",
+            "############",
+        ]);
+        let open = open_tiles(&map);
+        for a in &open {
+            let from_a = field_of_view(&map, a, 8);
+            for b in &open {
+                let from_b = field_of_view(&map, b, 8);
+                assert_eq!(from_a.contains(b), from_b.contains(a), "{:?} and {:?} disagree", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn walls_block_sight_but_are_seen() {
+        let map = SkirmMap::from_rows(&[
+            "#######",
+            "#..#..#",
+            "#######",
+        ]);
+        let visible = field_of_view(&map, &MapPoint::new(1, 1, 0), 8);
+        assert!(visible.contains(&MapPoint::new(2, 1, 0)));
+        assert!(visible.contains(&MapPoint::new(3, 1, 0)));
+        assert!(!visible.contains(&MapPoint::new(4, 1, 0)));
+        assert!(!visible.contains(&MapPoint::new(5, 1, 0)));
+    }
+
+    #[test]
+    fn pillars_cast_shadows() {
+        let map = SkirmMap::from_rows(&[
+            "#########",
+            "#.......#",
+            "#...#...#",
+            "#.......#",
+            "#########",
+        ]);
+        let visible = field_of_view(&map, &MapPoint::new(1, 2, 0), 8);
+        assert!(visible.contains(&MapPoint::new(4, 2, 0)));
+        assert!(!visible.contains(&MapPoint::new(6, 2, 0)));
+        assert!(visible.contains(&MapPoint::new(7, 1, 0)));
+    }
+
+    #[test]
+    fn sight_stops_at_the_radius() {
+        let map = SkirmMap::from_rows(&[
+            "##########",
+            "#........#",
+            "##########",
+        ]);
+        let visible = field_of_view(&map, &MapPoint::new(1, 1, 0), 3);
+        assert!(visible.contains(&MapPoint::new(4, 1, 0)));
+        assert!(!visible.contains(&MapPoint::new(5, 1, 0)));
+    }
+}
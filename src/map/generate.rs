@@ -0,0 +1,429 @@
+use crate::map::{MapPoint, MapError, Legend, LegendEntry, TileGrid, TileType, NavGraph, NavProfile};
+
+// Seeds tried for one level before giving up on finding a route through it
+const MAX_ATTEMPTS: u64 = 16;
+
+/// The legend glyphs a generated level is drawn with.
+struct Glyphs {
+    wall: char,
+    ground: char,
+    spawn: char,
+    elevator: char,
+}
+
+impl Glyphs {
+    fn from_legend(legend: &Legend) -> Result<Self, MapError> {
+        Ok(Self {
+            wall: find_glyph(legend, "wall", |entry| entry.solid && entry.tile_type == Some(TileType::Wall))?,
+            ground: find_glyph(legend, "ground", |entry| is_ground(entry) && !entry.spawn && !entry.elevator)?,
+            spawn: find_glyph(legend, "spawn", |entry| is_ground(entry) && entry.spawn)?,
+            elevator: find_glyph(legend, "elevator", |entry| is_ground(entry) && entry.elevator)?,
+        })
+    }
+}
+
+fn find_glyph<F>(legend: &Legend, name: &'static str, matches: F) -> Result<char, MapError>
+    where F: Fn(&LegendEntry) -> bool,
+{
+    legend.find_glyph(matches).ok_or_else(|| MapError::MissingGlyph(legend.path().to_path_buf(), name))
+}
+
+fn is_ground(entry: &LegendEntry) -> bool {
+    !entry.solid && entry.tile_type == Some(TileType::Ground)
+}
+
+/// A single generated z-level, ready for `SkirmMap::from_levels`.
+#[derive(Debug, Clone)]
+pub struct GeneratedLevel {
+    pub rows: Vec<Vec<char>>,
+    pub spawn: MapPoint,
+    pub elevator: MapPoint,
+}
+
+/// Seeded cavern generator. The same seed and settings always give the same
+/// level, and a level is only handed out once there's a walkable route from
+/// spawn down to the elevator.
+#[derive(Debug, Clone)]
+pub struct LevelGenerator {
+    pub width: i32,
+    pub height: i32,
+    // Percent chance that an interior tile starts out as rock
+    pub fill_percent: u32,
+    pub smoothing_passes: u32,
+    // Caverns smaller than this are filled in instead of connected
+    pub min_cavern_size: usize,
+    pub profile: NavProfile,
+}
+
+impl LevelGenerator {
+    pub fn new(width: i32, height: i32, profile: NavProfile) -> Self {
+        Self {
+            width,
+            height,
+            fill_percent: 45,
+            smoothing_passes: 5,
+            min_cavern_size: 12,
+            profile,
+        }
+    }
+
+    /// Generates the level for `seed` with `legend`'s wall, ground, spawn and
+    /// elevator glyphs. Caverns that leave no route from the spawn to the
+    /// elevator are thrown away for ones from seeds derived from `seed`.
+    pub fn generate(&self, seed: u64, legend: &Legend) -> Result<GeneratedLevel, MapError> {
+        let glyphs = Glyphs::from_legend(legend)?;
+        for attempt in 0..MAX_ATTEMPTS {
+            let attempt_seed = seed.wrapping_add(attempt.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let level = self.generate_once(attempt_seed, &glyphs);
+            if self.has_route(&level, legend) {
+                return Ok(level);
+            }
+            warn!("Generated level for seed {} has no route from {:?} to {:?}", attempt_seed, level.spawn, level.elevator);
+        }
+        Err(MapError::NoRoute(seed))
+    }
+
+    fn generate_once(&self, seed: u64, glyphs: &Glyphs) -> GeneratedLevel {
+        let mut rng = Rng::new(seed);
+        let mut cells = Cells::new(self.width, self.height);
+
+        cells.random_fill(self.fill_percent, &mut rng);
+        for _ in 0..self.smoothing_passes {
+            cells = cells.smoothed();
+        }
+        self.connect_caverns(&mut cells);
+        self.add_platforms(&mut cells, &mut rng);
+
+        // Spawn at the top of the level, the elevator at the bottom
+        let standable = cells.standable();
+        let spawn = standable.first().cloned().unwrap_or(MapPoint::new(1, 1, 0));
+        let elevator = standable.last().cloned().unwrap_or(MapPoint::new(self.width - 2, self.height - 2, 0));
+        cells.carve_route(&spawn, &elevator);
+
+        let mut rows = cells.to_rows(glyphs);
+        rows[spawn.y as usize][spawn.x as usize] = glyphs.spawn;
+        rows[elevator.y as usize][elevator.x as usize] = glyphs.elevator;
+
+        GeneratedLevel { rows, spawn, elevator }
+    }
+
+    /// Whether a walker with the generator's profile can get from the spawn
+    /// to the elevator. The spawn may sit over the top of a shaft, so the
+    /// route starts wherever it lands.
+    pub fn has_route(&self, level: &GeneratedLevel, legend: &Legend) -> bool {
+        let grid = TileGrid::from_glyphs(&[level.rows.clone()], legend);
+        let nav = NavGraph::build(&grid, &self.profile, 0);
+        nav.ground_node(&grid, &level.spawn)
+            .and_then(|start| nav.pathfind(&start, &level.elevator))
+            .is_some()
+    }
+
+    /// Keeps the largest cavern, fills in the tiny ones and tunnels every
+    /// other cavern through to the largest.
+    fn connect_caverns(&self, cells: &mut Cells) {
+        let mut caverns = cells.caverns();
+        if caverns.is_empty() {
+            return;
+        }
+
+        let main_index = (0..caverns.len()).fold(0, |best, i| {
+            if caverns[i].len() > caverns[best].len() { i } else { best }
+        });
+        let main = caverns.swap_remove(main_index);
+
+        for cavern in caverns {
+            if cavern.len() < self.min_cavern_size {
+                for &(x, y) in &cavern {
+                    cells.set(x, y, true);
+                }
+                continue;
+            }
+
+            let from = cavern[0];
+            let to = main.iter().cloned()
+                .min_by_key(|&(x, y)| (x - from.0).abs() + (y - from.1).abs())
+                .expect("Main cavern is not empty");
+            cells.carve_tunnel(from, to);
+        }
+    }
+
+    /// Breaks up tall open spaces with platforms one jump apart, in columns
+    /// about one running jump apart.
+    fn add_platforms(&self, cells: &mut Cells, rng: &mut Rng) {
+        let jump_height = self.profile.jump_height.max(1);
+        let spacing = (self.profile.jump_reach() as i32).max(4);
+        let offset = rng.below(spacing as u32) as i32;
+
+        let mut x = 2 + offset;
+        while x < self.width - 2 {
+            let mut open_run = 0;
+            for y in (1..self.height - 1).rev() {
+                if cells.get(x, y) {
+                    open_run = 0;
+                    continue;
+                }
+
+                open_run += 1;
+                let fits = (x - 1..x + 2).all(|px| !cells.get(px, y) && !cells.get(px, y - 1) && !cells.get(px, y - 2));
+                if open_run == jump_height && fits {
+                    for px in x - 1..x + 2 {
+                        cells.set(px, y, true);
+                    }
+                    open_run = 0;
+                }
+            }
+            x += spacing;
+        }
+    }
+}
+
+/// Rock or open space for every tile while a level is being generated. The
+/// border is always rock.
+#[derive(Clone)]
+struct Cells {
+    width: i32,
+    height: i32,
+    solid: Vec<bool>,
+}
+
+impl Cells {
+    fn new(width: i32, height: i32) -> Self {
+        Self { width, height, solid: vec![true; (width * height) as usize] }
+    }
+
+    fn is_border(&self, x: i32, y: i32) -> bool {
+        x <= 0 || y <= 0 || x >= self.width - 1 || y >= self.height - 1
+    }
+
+    fn get(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return true;
+        }
+        self.solid[(y * self.width + x) as usize]
+    }
+
+    fn set(&mut self, x: i32, y: i32, solid: bool) {
+        if !self.is_border(x, y) {
+            self.solid[(y * self.width + x) as usize] = solid;
+        }
+    }
+
+    fn random_fill(&mut self, fill_percent: u32, rng: &mut Rng) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let solid = rng.below(100) < fill_percent;
+                self.set(x, y, solid);
+            }
+        }
+    }
+
+    /// One cellular automaton pass: rock surrounded by open space crumbles and
+    /// open space surrounded by rock fills in.
+    fn smoothed(&self) -> Self {
+        let mut next = self.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut walls = 0;
+                for ny in y - 1..y + 2 {
+                    for nx in x - 1..x + 2 {
+                        if (nx, ny) != (x, y) && self.get(nx, ny) {
+                            walls += 1;
+                        }
+                    }
+                }
+
+                if walls > 4 {
+                    next.set(x, y, true);
+                } else if walls < 4 {
+                    next.set(x, y, false);
+                }
+            }
+        }
+        next
+    }
+
+    /// Open tiles grouped by 4-way connectivity, each in row order.
+    fn caverns(&self) -> Vec<Vec<(i32, i32)>> {
+        let mut seen = vec![false; self.solid.len()];
+        let mut caverns = Vec::new();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = (y * self.width + x) as usize;
+                if self.solid[index] || seen[index] {
+                    continue;
+                }
+
+                let mut cavern = Vec::new();
+                let mut stack = vec![(x, y)];
+                seen[index] = true;
+                while let Some((cx, cy)) = stack.pop() {
+                    cavern.push((cx, cy));
+                    for &(dx, dy) in [(0, -1), (-1, 0), (1, 0), (0, 1)].iter() {
+                        let (nx, ny) = (cx + dx, cy + dy);
+                        if self.get(nx, ny) {
+                            continue;
+                        }
+                        let next = (ny * self.width + nx) as usize;
+                        if !seen[next] {
+                            seen[next] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+                cavern.sort_by_key(|&(x, y)| (y, x));
+                caverns.push(cavern);
+            }
+        }
+
+        caverns
+    }
+
+    /// Digs an L-shaped tunnel two tiles tall, across and then down or up.
+    fn carve_tunnel(&mut self, from: (i32, i32), to: (i32, i32)) {
+        let (mut x, mut y) = from;
+        while x != to.0 {
+            self.set(x, y, false);
+            self.set(x, y - 1, false);
+            x += (to.0 - x).signum();
+        }
+        while y != to.1 {
+            self.set(x, y, false);
+            self.set(x, y - 1, false);
+            y += (to.1 - y).signum();
+        }
+        self.set(x, y, false);
+    }
+
+    /// Carves a staircase from `from` down to `to`. Every step sideways gets a
+    /// floor and drops at most one tile, so any walker can follow it.
+    fn carve_route(&mut self, from: &MapPoint, to: &MapPoint) {
+        let mut current = *from;
+        loop {
+            self.set(current.x, current.y, false);
+            self.set(current.x, current.y - 1, false);
+
+            let dx = (to.x - current.x).signum();
+            let dy = if to.y > current.y { 1 } else { 0 };
+            if dx == 0 && dy == 0 {
+                self.set(current.x, current.y + 1, true);
+                break;
+            }
+
+            if dx != 0 {
+                self.set(current.x, current.y + 1, true);
+            }
+            current = MapPoint::new(current.x + dx, current.y + dy, current.z);
+        }
+    }
+
+    /// Open tiles with rock underneath, in row order.
+    fn standable(&self) -> Vec<MapPoint> {
+        let mut points = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.get(x, y) && self.get(x, y + 1) {
+                    points.push(MapPoint::new(x, y, 0));
+                }
+            }
+        }
+        points
+    }
+
+    fn to_rows(&self, glyphs: &Glyphs) -> Vec<Vec<char>> {
+        (0..self.height)
+            .map(|y| (0..self.width).map(|x| if self.get(x, y) { glyphs.wall } else { glyphs.ground }).collect())
+            .collect()
+    }
+}
+
+/// A small xorshift generator, so levels don't change when a dependency's
+/// random number algorithm does.
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Run the seed through splitmix64 so neighbouring seeds give unrelated levels
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self { state: if z == 0 { 1 } else { z } }
+    }
+
+    fn next(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{components::JumpParams, systems::MOVE_SPEED};
+
+    const SHARED_LEGEND: &str = "./resources/maps/legend.ron";
+
+    fn generator() -> LevelGenerator {
+        LevelGenerator::new(40, 60, NavProfile::from_jump(&JumpParams::default(), MOVE_SPEED))
+    }
+
+    fn legend() -> Legend {
+        Legend::load(SHARED_LEGEND).expect("The shared legend loads")
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_level() {
+        let (generator, legend) = (generator(), legend());
+        let first = generator.generate(42, &legend).expect("Level generates");
+        let second = generator.generate(42, &legend).expect("Level generates");
+        assert_eq!(first.rows, second.rows);
+        assert_eq!(first.spawn, second.spawn);
+        assert_eq!(first.elevator, second.elevator);
+    }
+
+    #[test]
+    fn different_seeds_give_different_levels() {
+        let (generator, legend) = (generator(), legend());
+        let first = generator.generate(1, &legend).expect("Level generates");
+        let second = generator.generate(2, &legend).expect("Level generates");
+        assert_ne!(first.rows, second.rows);
+    }
+
+    #[test]
+    fn every_level_has_a_route_to_the_elevator() {
+        let (generator, legend) = (generator(), legend());
+        for seed in 0..10 {
+            let level = generator.generate(seed, &legend).expect("Level generates");
+            assert!(generator.has_route(&level, &legend), "Seed {} has no route", seed);
+            assert_eq!(level.rows[level.spawn.y as usize][level.spawn.x as usize], '@');
+            assert_eq!(level.rows[level.elevator.y as usize][level.elevator.x as usize], 'E');
+        }
+    }
+
+    #[test]
+    fn legend_without_a_spawn_glyph_is_an_error() {
+        let path = std::env::temp_dir().join("aok_generate_no_spawn_legend.ron");
+        fs::write(&path, r#"{
+            '#': (tile_type: Some(Wall), sprite: None, solid: true),
+            '.': (tile_type: Some(Ground), sprite: None, solid: false),
+            'E': (tile_type: Some(Ground), sprite: None, solid: false, elevator: true),
+        }"#).expect("Temp legend is written");
+        let legend = Legend::load(&path).expect("Temp legend loads");
+
+        assert_eq!(generator().generate(1, &legend).err(), Some(MapError::MissingGlyph(path.clone(), "spawn")));
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,102 @@
+use crate::map::{MapPoint, Tile, Legend};
+
+/// Tiles stored densely, row-major within each z-level and level after level.
+#[derive(Debug, Clone)]
+pub struct TileGrid {
+    width: i32,
+    height: i32,
+    levels: i32,
+    tiles: Vec<Tile>,
+}
+
+impl TileGrid {
+    /// Builds a grid from `tiles` laid out row-major, z-level after z-level.
+    pub fn from_tiles(width: i32, height: i32, levels: i32, tiles: Vec<Tile>) -> Self {
+        assert_eq!(tiles.len(), (width * height * levels) as usize, "Tile count doesn't match the grid size");
+        Self { width, height, levels, tiles }
+    }
+
+    /// Builds a grid from rows of glyphs, one set per z-level, all of which
+    /// must be in `legend`. The tiles have no entities yet.
+    pub fn from_glyphs(levels: &[Vec<Vec<char>>], legend: &Legend) -> Self {
+        let width = levels.first().and_then(|level| level.first()).map_or(0, |row| row.len());
+        let height = levels.first().map_or(0, |level| level.len());
+
+        let mut tiles = Vec::with_capacity(width * height * levels.len());
+        for level in levels {
+            for row in level {
+                for glyph in row {
+                    let entry = legend.get(*glyph).expect("Glyphs are checked against the legend when read");
+                    tiles.push(Tile::new(*glyph, entry));
+                }
+            }
+        }
+
+        Self::from_tiles(width as i32, height as i32, levels.len() as i32, tiles)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn levels(&self) -> i32 {
+        self.levels
+    }
+
+    pub fn in_bounds(&self, point: &MapPoint) -> bool {
+        point.x >= 0 && point.x < self.width
+            && point.y >= 0 && point.y < self.height
+            && point.z >= 0 && point.z < self.levels
+    }
+
+    fn index(&self, point: &MapPoint) -> Option<usize> {
+        if self.in_bounds(point) {
+            Some(((point.z * self.height + point.y) * self.width + point.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn point(&self, index: usize) -> MapPoint {
+        let index = index as i32;
+        let per_level = self.width * self.height;
+        let in_level = index % per_level;
+        MapPoint::new(in_level % self.width, in_level / self.width, index / per_level)
+    }
+
+    pub fn get(&self, point: &MapPoint) -> Option<&Tile> {
+        match self.index(point) {
+            Some(i) => Some(&self.tiles[i]),
+            None => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, point: &MapPoint) -> Option<&mut Tile> {
+        match self.index(point) {
+            Some(i) => Some(&mut self.tiles[i]),
+            None => None,
+        }
+    }
+
+    pub fn iter<'g>(&'g self) -> impl Iterator<Item = (MapPoint, &'g Tile)> + 'g {
+        self.tiles.iter().enumerate().map(move |(i, tile)| (self.point(i), tile))
+    }
+
+    /// Iterates the tiles between `min` and `max` inclusive, clipped to the grid.
+    pub fn region<'g>(&'g self, min: &MapPoint, max: &MapPoint) -> impl Iterator<Item = (MapPoint, &'g Tile)> + 'g {
+        let (x0, x1) = (min.x.max(0), max.x.min(self.width - 1));
+        let (y0, y1) = (min.y.max(0), max.y.min(self.height - 1));
+        let (z0, z1) = (min.z.max(0), max.z.min(self.levels - 1));
+
+        (z0..z1 + 1)
+            .flat_map(move |z| (y0..y1 + 1).flat_map(move |y| (x0..x1 + 1).map(move |x| MapPoint::new(x, y, z))))
+            .map(move |point| {
+                let i = ((point.z * self.height + point.y) * self.width + point.x) as usize;
+                (point, &self.tiles[i])
+            })
+    }
+}
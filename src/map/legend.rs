@@ -0,0 +1,70 @@
+use ron;
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::map::{TileType, MapError};
+
+/// What a single map glyph turns into when a map is loaded.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct LegendEntry {
+    pub tile_type: Option<TileType>,
+    pub sprite: Option<String>,
+    pub solid: bool,
+    #[serde(default)]
+    pub spawn: bool,
+    #[serde(default)]
+    pub elevator: bool,
+    // Strength needed to smash a secret wall open by walking into it, walls
+    // without one can't be smashed
+    #[serde(default)]
+    pub smash_strength: Option<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Legend {
+    entries: HashMap<char, LegendEntry>,
+    path: PathBuf,
+}
+
+impl Legend {
+    pub fn load<P>(path: P) -> Result<Self, MapError>
+        where P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let io_error = |e: std::io::Error| MapError::Io(path.to_path_buf(), e.to_string());
+
+        // Legend - open the file, read it into a buffer, deserialize with serde
+        let mut legend_file = File::open(path).map_err(io_error)?;
+        let mut buffer = String::new();
+        legend_file.read_to_string(&mut buffer).map_err(io_error)?;
+        let entries: HashMap<char, LegendEntry> = ron::de::from_str(buffer.as_str())
+            .map_err(|e| MapError::BadLegend(path.to_path_buf(), format!("{:?}", e)))?;
+
+        Ok(Self { entries, path: path.to_path_buf() })
+    }
+
+    /// The file this legend was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, glyph: char) -> Option<&LegendEntry> {
+        self.entries.get(&glyph)
+    }
+
+    /// The lowest glyph whose entry matches, so the choice doesn't depend on
+    /// hash order.
+    pub fn find_glyph<F>(&self, matches: F) -> Option<char>
+        where F: Fn(&LegendEntry) -> bool,
+    {
+        self.entries.iter()
+            .filter(|(_, entry)| matches(entry))
+            .map(|(glyph, _)| *glyph)
+            .min()
+    }
+}
@@ -0,0 +1,80 @@
+use ron;
+
+use std::{
+    fs::File,
+    io::Read,
+    path::Path,
+};
+
+use crate::map::{find_marker, Legend, MapError, MapPoint};
+
+/// An enemy to create when the level is entered, named after its entry in
+/// "skirmers.ron".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnemySpawner {
+    pub skirmer: String,
+    pub point: MapPoint,
+}
+
+/// An item lying on the level, named after its entry in "weapons.ron".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemPickup {
+    pub item: String,
+    pub point: MapPoint,
+}
+
+/// What a map places on itself, kept in "meta.ron" next to its levels. Every
+/// field is optional in the file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MapMeta {
+    #[serde(default)]
+    pub name: String,
+    // The first one is where the player starts
+    #[serde(default)]
+    pub player_spawns: Vec<MapPoint>,
+    #[serde(default)]
+    pub enemy_spawners: Vec<EnemySpawner>,
+    #[serde(default)]
+    pub items: Vec<ItemPickup>,
+    #[serde(default)]
+    pub elevator: Option<MapPoint>,
+}
+
+impl MapMeta {
+    pub fn new(name: String) -> Self {
+        Self { name, ..Default::default() }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MapError> {
+        let path = path.as_ref();
+        let io_error = |e: std::io::Error| MapError::Io(path.to_path_buf(), e.to_string());
+
+        let mut meta_file = File::open(path).map_err(io_error)?;
+        let mut buffer = String::new();
+        meta_file.read_to_string(&mut buffer).map_err(io_error)?;
+
+        ron::de::from_str(buffer.as_str())
+            .map_err(|e| MapError::BadMeta(path.to_path_buf(), format!("{:?}", e)))
+    }
+
+    /// Folds in the spawn and elevator glyphs drawn on the levels. Spawns in
+    /// the metadata come first, and an elevator in the metadata wins over one
+    /// drawn on the map.
+    pub fn add_markers(&mut self, levels: &[Vec<Vec<char>>], legend: &Legend) {
+        for (z, level) in levels.iter().enumerate() {
+            for (j, row) in level.iter().enumerate() {
+                for (i, glyph) in row.iter().enumerate() {
+                    let point = MapPoint::new(i as i32, j as i32, z as i32);
+                    let is_spawn = legend.get(*glyph).map_or(false, |entry| entry.spawn);
+                    if is_spawn && !self.player_spawns.contains(&point) {
+                        self.player_spawns.push(point);
+                    }
+                }
+            }
+        }
+
+        if self.elevator.is_none() {
+            self.elevator = find_marker(levels, legend, |entry| entry.elevator);
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use pathfinding::dijkstra;
+
+use std::collections::HashMap;
+
+use crate::{
+    components::JumpParams,
+    map::{MapPoint, SkirmMap, TileGrid, TILE_WIDTH, TILE_HEIGHT},
+    systems::{GRAVITY, MOVE_SPEED},
+};
+
+/// Time step used to trace jump arcs, in seconds.
+const ARC_STEP: f32 = 0.02;
+/// Longest a traced jump is allowed to stay in the air, in seconds.
+const MAX_AIR_TIME: f32 = 3.0;
+
+/// How far and how high a walker can get, used to decide which links exist.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct NavProfile {
+    // Whole tiles of height a full jump clears
+    pub jump_height: i32,
+    // Tiles per second when running
+    pub run_speed: f32,
+    // Tiles per second squared pulling jumps back down
+    pub gravity: f32,
+}
+
+impl NavProfile {
+    /// A skirmer jumping with `params` and running at `run_speed` pixels per
+    /// second, falling the way `PhysicsSys` moves it.
+    pub fn from_jump(params: &JumpParams, run_speed: f32) -> Self {
+        let peak = params.speed * params.speed / (2.0 * GRAVITY);
+        Self {
+            jump_height: (peak / TILE_HEIGHT as f32) as i32,
+            run_speed: run_speed / TILE_WIDTH as f32,
+            gravity: GRAVITY / TILE_HEIGHT as f32,
+        }
+    }
+
+    /// How many tiles across a full height running jump carries, landing at
+    /// the height it started from.
+    pub fn jump_reach(&self) -> f32 {
+        let air_time = 2.0 * (2.0 * self.jump_height as f32 / self.gravity).sqrt();
+        self.run_speed * air_time
+    }
+}
+
+/// Navigation graphs of the current z-level, built the first time a profile
+/// asks for one. They go stale when the terrain changes or the level is
+/// replaced, and have to be cleared then.
+#[derive(Default)]
+pub struct NavGraphs {
+    graphs: Vec<(NavProfile, NavGraph)>,
+}
+
+impl NavGraphs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.graphs.clear();
+    }
+
+    pub fn graph(&mut self, map: &SkirmMap, profile: &NavProfile) -> &NavGraph {
+        let index = match self.graphs.iter().position(|(p, _)| p == profile) {
+            Some(index) => index,
+            None => {
+                self.graphs.push((*profile, NavGraph::build(&map.map, profile, map.current_z)));
+                self.graphs.len() - 1
+            },
+        };
+        &self.graphs[index].1
+    }
+}
+
+/// A single movement a walker performs to get from one standable tile to another.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum NavAction {
+    Walk(MapPoint),
+    Drop(MapPoint),
+    Jump { to: MapPoint, height: i32 },
+}
+
+impl NavAction {
+    pub fn target(&self) -> MapPoint {
+        match *self {
+            NavAction::Walk(to) | NavAction::Drop(to) => to,
+            NavAction::Jump { to, .. } => to,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NavLink {
+    action: NavAction,
+    cost: usize,
+}
+
+/// Standable surfaces of one z-level and the walks, drops and jumps between them.
+#[derive(Debug)]
+pub struct NavGraph {
+    links: HashMap<MapPoint, Vec<NavLink>>,
+}
+
+impl NavGraph {
+    pub fn build(map: &TileGrid, profile: &NavProfile, z: i32) -> Self {
+        let (width, height) = (map.width(), map.height());
+        let mut links = HashMap::new();
+
+        for y in 0..height {
+            for x in 0..width {
+                let from = MapPoint::new(x, y, z);
+                if !is_standable(map, &from) {
+                    continue;
+                }
+
+                let mut node_links = Vec::new();
+                for dir in [-1, 1].iter() {
+                    add_walk_or_drop(map, &from, *dir, &mut node_links);
+                    for jump_height in 1..profile.jump_height + 1 {
+                        add_jump(map, profile, &from, *dir, jump_height, &mut node_links);
+                    }
+                }
+                links.insert(from, node_links);
+            }
+        }
+
+        Self { links }
+    }
+
+    pub fn is_node(&self, point: &MapPoint) -> bool {
+        self.links.contains_key(point)
+    }
+
+    /// The standable tile a walker at `point` ends up on if it falls straight down.
+    pub fn ground_node(&self, map: &TileGrid, point: &MapPoint) -> Option<MapPoint> {
+        let mut below = *point;
+        while map.in_bounds(&below) {
+            if self.is_node(&below) {
+                return Some(below);
+            }
+            below.y += 1;
+        }
+        None
+    }
+
+    /// Finds the cheapest chain of actions between two standable tiles.
+    pub fn pathfind(&self, from: &MapPoint, to: &MapPoint) -> Option<Vec<NavAction>> {
+        let (points, _cost) = dijkstra(
+            from,
+            |p| self.links.get(p).into_iter()
+                .flat_map(|links| links.iter())
+                .map(|link| (link.action.target(), link.cost))
+                .collect::<Vec<_>>(),
+            |p| *p == *to,
+        )?;
+
+        let mut actions = Vec::new();
+        for pair in points.windows(2) {
+            let link = self.links[&pair[0]].iter()
+                .filter(|link| link.action.target() == pair[1])
+                .min_by_key(|link| link.cost)
+                .expect("Dijkstra only follows existing links");
+            actions.push(link.action);
+        }
+        Some(actions)
+    }
+}
+
+fn is_open(map: &TileGrid, point: &MapPoint) -> bool {
+    map.get(point).map_or(false, |tile| !tile.solid)
+}
+
+/// Open tiles with something solid, a platform or the top of a ladder
+/// underneath, and slopes. Like `SkirmMap::surface_between`, a ladder only
+/// holds up whatever is above its top tile, not the rest of its column.
+fn is_standable(map: &TileGrid, point: &MapPoint) -> bool {
+    let below = MapPoint::new(point.x, point.y + 1, point.z);
+    let tile_type = map.get(point).and_then(|tile| tile.tile_type);
+    let on_slope = tile_type.map_or(false, |t| t.slope().is_some());
+    let on_ladder = tile_type.map_or(false, |t| t.is_climbable());
+    let held_up = map.get(&below).map_or(false, |tile| {
+        tile.solid || tile.tile_type.map_or(false, |t| t.is_platform() || (t.is_climbable() && !on_ladder))
+    });
+    is_open(map, point) && (on_slope || held_up)
+}
+
+/// Walks onto the neighbouring surface, or steps off the ledge and falls
+/// straight down until something is underfoot.
+fn add_walk_or_drop(map: &TileGrid, from: &MapPoint, dir: i32, links: &mut Vec<NavLink>) {
+    let side = MapPoint::new(from.x + dir, from.y, from.z);
+    if !is_open(map, &side) {
+        return;
+    }
+
+    if is_standable(map, &side) {
+        links.push(NavLink { action: NavAction::Walk(side), cost: 1 });
+        return;
+    }
+
+    let mut landing = side;
+    while is_open(map, &landing) {
+        if is_standable(map, &landing) {
+            let fall = landing.y - from.y;
+            links.push(NavLink { action: NavAction::Drop(landing), cost: 1 + fall as usize });
+            return;
+        }
+        landing.y += 1;
+    }
+}
+
+/// Traces a running jump that peaks `jump_height` tiles up and links to the
+/// first surface it comes down on, if nothing solid is in the way.
+fn add_jump(map: &TileGrid, profile: &NavProfile, from: &MapPoint, dir: i32, jump_height: i32, links: &mut Vec<NavLink>) {
+    let launch_speed = (2.0 * profile.gravity * jump_height as f32).sqrt();
+
+    let mut t = ARC_STEP;
+    while t < MAX_AIR_TIME {
+        let x = dir as f32 * profile.run_speed * t;
+        let y = -launch_speed * t + profile.gravity * t * t / 2.0;
+        let point = MapPoint::new(from.x + x.round() as i32, from.y + y.round() as i32, from.z);
+
+        if !is_open(map, &point) {
+            return;
+        }
+
+        let falling = profile.gravity * t > launch_speed;
+        if falling && point != *from && is_standable(map, &point) {
+            let distance = (point.x - from.x).abs() + (point.y - from.y).abs();
+            let cost = distance as usize + 2 * jump_height as usize;
+            links.push(NavLink { action: NavAction::Jump { to: point, height: jump_height }, cost });
+            return;
+        }
+
+        t += ARC_STEP;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(rows: &[&str], profile: &NavProfile, from: (i32, i32), to: (i32, i32)) -> Option<Vec<NavAction>> {
+        let map = SkirmMap::from_rows(rows);
+        let graph = NavGraph::build(&map.map, profile, 0);
+        graph.pathfind(&MapPoint::new(from.0, from.1, 0), &MapPoint::new(to.0, to.1, 0))
+    }
+
+    fn skirmer() -> NavProfile {
+        NavProfile::from_jump(&JumpParams::default(), MOVE_SPEED)
+    }
+
+    #[test]
+    fn profile_follows_the_physics() {
+        let profile = skirmer();
+        // 330 px/s against 600 px/s² peaks a little under three tiles up
+        assert_eq!(profile.jump_height, 2);
+        assert_eq!(profile.run_speed, MOVE_SPEED / TILE_WIDTH as f32);
+        assert_eq!(profile.gravity, GRAVITY / TILE_HEIGHT as f32);
+    }
+
+    #[test]
+    fn walks_along_the_floor() {
+        let actions = route(&[
+            ".....",
+            "#####",
+        ], &skirmer(), (0, 0), (4, 0)).expect("Floor is walkable");
+
+        let walks: Vec<NavAction> = (1..5).map(|x| NavAction::Walk(MapPoint::new(x, 0, 0))).collect();
+        assert_eq!(actions, walks);
+    }
+
+    #[test]
+    fn drops_off_a_ledge() {
+        let actions = route(&[
+            "......",
+            "###...",
+            "......",
+            "######",
+        ], &skirmer(), (0, 0), (5, 2)).expect("Ledge can be dropped off");
+
+        assert!(actions.contains(&NavAction::Drop(MapPoint::new(3, 2, 0))));
+        assert_eq!(actions.last().map(NavAction::target), Some(MapPoint::new(5, 2, 0)));
+    }
+
+    #[test]
+    fn jumps_onto_a_ledge_within_reach() {
+        let actions = route(&[
+            "......",
+            "......",
+            "......",
+            "....##",
+            "....##",
+            "######",
+        ], &skirmer(), (1, 4), (5, 2)).expect("Two tiles up is within jumping height");
+
+        assert!(actions.iter().any(|action| match action {
+            NavAction::Jump { to, .. } => *to == MapPoint::new(4, 2, 0),
+            _ => false,
+        }));
+        assert_eq!(actions.last().map(NavAction::target), Some(MapPoint::new(5, 2, 0)));
+    }
+
+    #[test]
+    fn only_the_top_of_a_ladder_is_stood_on() {
+        let map = SkirmMap::from_rows(&[
+            "....",
+            ".H..",
+            ".H..",
+            ".H..",
+            "####",
+        ]);
+        let graph = NavGraph::build(&map.map, &skirmer(), 0);
+
+        assert!(graph.is_node(&MapPoint::new(1, 0, 0)));
+        assert!(!graph.is_node(&MapPoint::new(1, 1, 0)));
+        assert!(!graph.is_node(&MapPoint::new(1, 2, 0)));
+        // The bottom stands on the floor like any other tile
+        assert!(graph.is_node(&MapPoint::new(1, 3, 0)));
+    }
+
+    #[test]
+    fn cannot_jump_onto_a_ledge_too_high() {
+        let rows = [
+            "......",
+            "......",
+            "......",
+            "....##",
+            "....##",
+            "....##",
+            "######",
+        ];
+        assert_eq!(route(&rows, &skirmer(), (1, 5), (5, 2)), None);
+
+        // Two tiles up is too much for a walker that only clears one
+        let low_jumper = NavProfile { jump_height: 1, ..skirmer() };
+        assert_eq!(route(&[
+            "......",
+            "......",
+            "......",
+            "....##",
+            "....##",
+            "######",
+        ], &low_jumper, (1, 4), (5, 2)), None);
+    }
+}
@@ -7,40 +7,41 @@ use crate::map::{
     tile::TileType,
 };
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct MapPoint {
     pub x: i32,
     pub y: i32,
+    pub z: i32,
 }
 
 impl MapPoint {
-    pub fn new(x: i32, y: i32) -> Self {
-        Self { x, y }
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
     }
 
     pub fn as_float_coord_tuple(&self) -> (f32, f32) {
         ((self.x * TILE_WIDTH) as f32, (self.y * TILE_HEIGHT) as f32)
     }
 
-    pub fn from_pixel_coord(x: i32, y: i32) -> Self {
+    pub fn from_pixel_coord(x: i32, y: i32, z: i32) -> Self {
         let tile_index_x = x / TILE_WIDTH;
         let tile_index_y = y / TILE_HEIGHT;
-        MapPoint::new(tile_index_x, tile_index_y)
+        MapPoint::new(tile_index_x, tile_index_y, z)
     }
 
-    fn offset(&self, x: i32, y: i32) -> Self {
-        MapPoint { x: self.x + x, y: self.y + y }
+    pub fn offset(&self, x: i32, y: i32) -> Self {
+        MapPoint { x: self.x + x, y: self.y + y, z: self.z }
     }
 
     pub fn neighbors(&self, map: &SkirmMap) -> Vec<(Self, usize)> {
-        let mut neighbors = Vec::new();
-        let points_to_check = vec![
+        let mut neighbors = Vec::with_capacity(8);
+        let points_to_check = [
             (-1, -1), (0, -1), (1, -1),
             (-1, 0),           (1, 0),
             (-1, 1),  (0, 1),  (1, 1)
         ];
         let mut i = -1;
-        for to_check in points_to_check {
+        for to_check in points_to_check.iter() {
             i += 1;
             let next_point = self.offset(to_check.0, to_check.1);
             let neighbor = match map.map.get(&next_point) {
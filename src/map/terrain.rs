@@ -0,0 +1,55 @@
+use specs::{Entities, WriteStorage};
+
+use crate::{
+    CollisionWorld,
+    components::{PositionComp, SpriteComp, CollideComp},
+    map::MapPoint,
+};
+
+/// A change to the terrain requested by gameplay code, applied by `TerrainSys`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TerrainEdit {
+    Dig(MapPoint),
+    Place(MapPoint, char),
+    Reveal(MapPoint),
+    // Center and radius in tiles
+    Explosion(MapPoint, i32),
+}
+
+pub struct TerrainEdits {
+    pub edits: Vec<TerrainEdit>,
+}
+
+impl TerrainEdits {
+    pub fn new() -> Self {
+        Self { edits: Vec::new() }
+    }
+}
+
+/// Emitted whenever a tile changes glyph while the game runs.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct TerrainEvent {
+    pub point: MapPoint,
+}
+
+/// The terrain changes made this tick. Cleared at the start of every update,
+/// so systems that react to them should run after "terrain".
+pub struct TerrainEvents {
+    pub events: Vec<TerrainEvent>,
+}
+
+impl TerrainEvents {
+    pub fn new() -> Self {
+        Self { events: Vec::new() }
+    }
+}
+
+/// Everything outside of `SkirmMap` that a tile change has to touch.
+pub struct TerrainAccess<'s, 'a: 's> {
+    pub entities: &'s Entities<'a>,
+    pub positions: &'s mut WriteStorage<'a, PositionComp>,
+    pub sprites: &'s mut WriteStorage<'a, SpriteComp>,
+    pub collide: &'s mut WriteStorage<'a, CollideComp>,
+    pub collide_world: &'s mut CollisionWorld,
+    pub events: &'s mut TerrainEvents,
+}
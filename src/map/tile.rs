@@ -1,9 +1,69 @@
 use specs::Entity;
 
-#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
+use crate::map::{
+    TILE_WIDTH,
+    TILE_HEIGHT,
+    legend::LegendEntry,
+};
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy, Deserialize)]
 pub enum TileType {
     Wall,
+    SecretWall,
     Ground,
+    // Can be jumped up through from below and dropped through with down
+    Platform,
+    Slope(Slope),
+    // Climbable, the top of each one can be stood on like a platform
+    Ladder,
+    Vine,
+}
+
+impl TileType {
+    pub fn is_platform(&self) -> bool {
+        *self == TileType::Platform
+    }
+
+    pub fn is_climbable(&self) -> bool {
+        *self == TileType::Ladder || *self == TileType::Vine
+    }
+
+    pub fn slope(&self) -> Option<Slope> {
+        match self {
+            TileType::Slope(slope) => Some(*slope),
+            _ => None,
+        }
+    }
+}
+
+/// Which way a slope rises going right. Steep slopes are 45 degrees, gentle
+/// ones climb half a tile per tile so they come in a low and a high half.
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy, Deserialize)]
+pub enum Slope {
+    Up,
+    Down,
+    GentleUpLow,
+    GentleUpHigh,
+    GentleDownHigh,
+    GentleDownLow,
+}
+
+impl Slope {
+    /// Height of the surface above the tile's bottom edge, `local_x` pixels in
+    /// from its left edge.
+    pub fn height_at(&self, local_x: f32) -> f32 {
+        let width = TILE_WIDTH as f32;
+        let height = TILE_HEIGHT as f32;
+        let t = local_x.max(0.0).min(width) / width;
+        match self {
+            Slope::Up => t * height,
+            Slope::Down => (1.0 - t) * height,
+            Slope::GentleUpLow => t * height / 2.0,
+            Slope::GentleUpHigh => (1.0 + t) * height / 2.0,
+            Slope::GentleDownHigh => (2.0 - t) * height / 2.0,
+            Slope::GentleDownLow => (1.0 - t) * height / 2.0,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -11,15 +71,26 @@ pub struct Tile {
     pub occupant: Option<Entity>,
     pub tile_type: Option<TileType>,
     pub glyph: char,
+    pub solid: bool,
+    pub entity: Option<Entity>,
 }
 
 impl Tile {
-    pub fn new(tile_type: Option<TileType>) -> Self {
-        Self { occupant: None, tile_type, glyph: '#' }
+    pub fn new(glyph: char, entry: &LegendEntry) -> Self {
+        Self { occupant: None, tile_type: entry.tile_type, glyph, solid: entry.solid, entity: None }
     }
 
     pub fn has_occupant(&self) -> bool {
         self.occupant.is_some()
     }
-}
 
+    /// How far below the tile's top edge something can stand, `local_x` pixels
+    /// in from its left edge, for platforms and slopes.
+    pub fn surface_at(&self, local_x: f32) -> Option<f32> {
+        match self.tile_type {
+            Some(TileType::Platform) => Some(0.0),
+            Some(TileType::Slope(slope)) => Some(TILE_HEIGHT as f32 - slope.height_at(local_x)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,532 @@
+//! Importing levels drawn in the Tiled map editor, from either its JSON or TMX
+//! format. Tile layers stack up as z-levels, and each tile in a tileset needs
+//! either a `glyph` property naming a legend glyph or a `tile_type` property
+//! naming a `TileType`. Objects sit on the tile layer below them and fill in
+//! the map's metadata by their type: `spawn`, `elevator`, `enemy` or `item`.
+//! Enemies and items are named after their skirmer or weapon.
+//!
+//! Tile layers need CSV or XML encoding without compression, and infinite
+//! maps aren't supported. Empty cells become the legend's empty glyph, and
+//! the level is named after the file.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use xml::{
+    attribute::OwnedAttribute,
+    reader::{EventReader, XmlEvent},
+};
+
+use crate::map::{EnemySpawner, ItemPickup, Legend, LegendEntry, MapError, MapMeta, MapPoint, TileType};
+
+// Tiled keeps flipping in the top bits of each gid
+const FLIP_FLAGS: u32 = 0xE000_0000;
+
+/// A Tiled map turned into legend glyphs, ready for `SkirmMap::from_levels`.
+#[derive(Debug)]
+pub struct TiledLevel {
+    pub levels: Vec<Vec<Vec<char>>>,
+    pub meta: MapMeta,
+}
+
+/// Reads the Tiled map at `path`, picking the format from its extension.
+pub fn import(path: &Path, legend: &Legend) -> Result<TiledLevel, MapError> {
+    let mut raw = RawMap::default();
+    match extension(path).as_str() {
+        "json" | "tmj" => read_json(path, &mut raw)?,
+        "tmx" => read_tmx(path, 1, &mut raw)?,
+        other => return Err(bad(path, format!("unknown map format {:?}", other))),
+    }
+    build_level(path, raw, legend)
+}
+
+/// Everything the importer needs from either format.
+#[derive(Default, Debug)]
+struct RawMap {
+    width: usize,
+    height: usize,
+    tile_width: f32,
+    tile_height: f32,
+    infinite: bool,
+    // Gids for each tile layer, row-major
+    layers: Vec<Vec<u32>>,
+    properties: HashMap<u32, HashMap<String, String>>,
+    objects: Vec<RawObject>,
+}
+
+#[derive(Debug)]
+struct RawObject {
+    name: String,
+    kind: String,
+    // Pixel position of the object's center
+    center: (f32, f32),
+    z: usize,
+}
+
+impl RawObject {
+    /// `rect` is the object's x, y, width and height. Tile objects hang up
+    /// from their y, everything else hangs down.
+    fn new(name: String, kind: String, rect: (f32, f32, f32, f32), tile: bool, z: usize) -> Self {
+        let (x, y, width, height) = rect;
+        let center_y = if tile { y - height / 2.0 } else { y + height / 2.0 };
+        Self {
+            name,
+            kind,
+            center: (x + width / 2.0, center_y),
+            z,
+        }
+    }
+}
+
+fn build_level(path: &Path, raw: RawMap, legend: &Legend) -> Result<TiledLevel, MapError> {
+    if raw.infinite {
+        return Err(bad(path, "infinite maps aren't supported".to_string()));
+    }
+    if raw.layers.is_empty() {
+        return Err(bad(path, "map has no tile layers".to_string()));
+    }
+
+    let mut glyphs = HashMap::new();
+    let mut levels = Vec::new();
+    for (z, layer) in raw.layers.iter().enumerate() {
+        if layer.len() != raw.width * raw.height {
+            return Err(bad(path, format!("tile layer {} is not {}x{}", z, raw.width, raw.height)));
+        }
+
+        let mut rows = Vec::new();
+        for row in layer.chunks(raw.width) {
+            let mut glyph_row = Vec::new();
+            for gid in row {
+                let gid = gid & !FLIP_FLAGS;
+                if !glyphs.contains_key(&gid) {
+                    glyphs.insert(gid, tile_glyph(path, gid, &raw, legend)?);
+                }
+                glyph_row.push(glyphs[&gid]);
+            }
+            rows.push(glyph_row);
+        }
+        levels.push(rows);
+    }
+
+    let name = path.file_stem().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+    let mut meta = MapMeta::new(name);
+    for object in raw.objects {
+        let point = MapPoint::new(
+            (object.center.0 / raw.tile_width).floor() as i32,
+            (object.center.1 / raw.tile_height).floor() as i32,
+            object.z as i32,
+        );
+        if point.x < 0 || point.y < 0 || point.x as usize >= raw.width || point.y as usize >= raw.height {
+            warn!("Skipping Tiled object {:?}, it's off the map", object.name);
+            continue;
+        }
+
+        match object.kind.to_lowercase().as_str() {
+            "spawn" => meta.player_spawns.push(point),
+            "elevator" => match meta.elevator {
+                Some(_) => warn!("Skipping Tiled object {:?}, the map already has an elevator", object.name),
+                None => meta.elevator = Some(point),
+            },
+            "enemy" => meta.enemy_spawners.push(EnemySpawner { skirmer: object.name, point }),
+            "item" => meta.items.push(ItemPickup { item: object.name, point }),
+            _ => warn!("Skipping Tiled object {:?} of unknown type {:?}", object.name, object.kind),
+        }
+    }
+
+    Ok(TiledLevel { levels, meta })
+}
+
+/// The legend glyph a tile stands for, from its `glyph` or `tile_type`
+/// property. Gid 0 is a gap in the layer and comes out empty.
+fn tile_glyph(path: &Path, gid: u32, raw: &RawMap, legend: &Legend) -> Result<char, MapError> {
+    let is_plain = |entry: &LegendEntry| !entry.spawn && !entry.elevator;
+    if gid == 0 {
+        return legend.find_glyph(|entry| entry.tile_type.is_none() && !entry.solid && is_plain(entry))
+            .ok_or_else(|| bad(path, "legend has no empty tile for gaps".to_string()));
+    }
+
+    let properties = raw.properties.get(&gid);
+    let property = |name: &str| properties.and_then(|p| p.get(name));
+
+    if let Some(glyph) = property("glyph") {
+        let mut chars = glyph.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(glyph), None) if legend.get(glyph).is_some() => Ok(glyph),
+            (Some(glyph), None) => Err(MapError::NotInLegend(glyph)),
+            _ => Err(bad(path, format!("tile {} has glyph {:?}, expected one character", gid, glyph))),
+        };
+    }
+
+    if let Some(tile_type) = property("tile_type") {
+        let tile_type: TileType = ron::de::from_str(tile_type)
+            .map_err(|_| bad(path, format!("tile {} has unknown tile_type {:?}", gid, tile_type)))?;
+        return legend.find_glyph(|entry| entry.tile_type == Some(tile_type) && is_plain(entry))
+            .ok_or_else(|| bad(path, format!("legend has no glyph for {:?}", tile_type)));
+    }
+
+    Err(bad(path, format!("tile {} has no glyph or tile_type property", gid)))
+}
+
+// JSON
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: usize,
+    height: usize,
+    tilewidth: f32,
+    tileheight: f32,
+    #[serde(default)]
+    infinite: bool,
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    // Only group layers have these
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    firstgid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    name: String,
+    // Called "class" since Tiled 1.9
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    gid: Option<u32>,
+}
+
+fn read_json(path: &Path, raw: &mut RawMap) -> Result<(), MapError> {
+    let file = File::open(path).map_err(|e| MapError::Io(path.to_path_buf(), e.to_string()))?;
+    let map: JsonMap = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| bad(path, e.to_string()))?;
+
+    raw.width = map.width;
+    raw.height = map.height;
+    raw.tile_width = map.tilewidth;
+    raw.tile_height = map.tileheight;
+    raw.infinite = map.infinite;
+
+    for tileset in map.tilesets {
+        match tileset.source {
+            Some(source) => read_tileset(&sibling(path, &source), tileset.firstgid, raw)?,
+            None => add_json_tiles(tileset.firstgid, tileset.tiles, raw),
+        }
+    }
+
+    add_json_layers(path, map.layers, raw)
+}
+
+fn add_json_layers(path: &Path, layers: Vec<JsonLayer>, raw: &mut RawMap) -> Result<(), MapError> {
+    for layer in layers {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                if layer.encoding.as_ref().map_or(false, |e| e != "csv") {
+                    return Err(bad(path, "tile layers need to use CSV encoding".to_string()));
+                }
+                let data = layer.data.ok_or_else(|| bad(path, "tile layer has no data".to_string()))?;
+                let gids: Vec<u32> = serde_json::from_value(data).map_err(|e| bad(path, e.to_string()))?;
+                raw.layers.push(gids);
+            },
+            "objectgroup" => {
+                let z = raw.layers.len().saturating_sub(1);
+                for object in layer.objects {
+                    let kind = if object.kind.is_empty() { object.class } else { object.kind };
+                    raw.objects.push(RawObject::new(
+                        object.name, kind, (object.x, object.y, object.width, object.height), object.gid.is_some(), z,
+                    ));
+                }
+            },
+            "group" => add_json_layers(path, layer.layers, raw)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn add_json_tiles(firstgid: u32, tiles: Vec<JsonTile>, raw: &mut RawMap) {
+    for tile in tiles {
+        let properties = tile.properties.into_iter()
+            .map(|p| {
+                let value = match p.value {
+                    serde_json::Value::String(s) => s,
+                    other => other.to_string(),
+                };
+                (p.name, value)
+            })
+            .collect();
+        raw.properties.insert(firstgid + tile.id, properties);
+    }
+}
+
+/// Loads a tileset kept in its own file, in either format.
+fn read_tileset(path: &Path, firstgid: u32, raw: &mut RawMap) -> Result<(), MapError> {
+    match extension(path).as_str() {
+        "json" | "tsj" => {
+            let file = File::open(path).map_err(|e| MapError::Io(path.to_path_buf(), e.to_string()))?;
+            let tileset: JsonTileset = serde_json::from_reader(BufReader::new(file))
+                .map_err(|e| bad(path, e.to_string()))?;
+            add_json_tiles(firstgid, tileset.tiles, raw);
+            Ok(())
+        },
+        "tsx" => read_tmx(path, firstgid, raw),
+        other => Err(bad(path, format!("unknown tileset format {:?}", other))),
+    }
+}
+
+// TMX
+
+/// Walks a TMX map or TSX tileset. `firstgid` is used for tilesets that don't
+/// give their own, which is how TSX files come.
+fn read_tmx(path: &Path, firstgid: u32, raw: &mut RawMap) -> Result<(), MapError> {
+    let file = File::open(path).map_err(|e| MapError::Io(path.to_path_buf(), e.to_string()))?;
+    let parser = EventReader::new(BufReader::new(file));
+
+    let mut tileset_gid = None;
+    let mut tile_gid = None;
+    let mut data: Option<Vec<u32>> = None;
+    let mut csv = String::new();
+
+    for event in parser {
+        match event.map_err(|e| bad(path, e.to_string()))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let attr = |key: &str| attribute(&attributes, key);
+                match name.local_name.as_str() {
+                    "map" => {
+                        raw.width = parse_attr(path, attr("width"))?;
+                        raw.height = parse_attr(path, attr("height"))?;
+                        raw.tile_width = parse_attr(path, attr("tilewidth"))?;
+                        raw.tile_height = parse_attr(path, attr("tileheight"))?;
+                        raw.infinite = attr("infinite") == Some("1");
+                    },
+                    "tileset" => {
+                        let gid = match attr("firstgid") {
+                            Some(gid) => parse_attr(path, Some(gid))?,
+                            None => firstgid,
+                        };
+                        match attr("source") {
+                            Some(source) => read_tileset(&sibling(path, source), gid, raw)?,
+                            None => tileset_gid = Some(gid),
+                        }
+                    },
+                    "tile" => match (&mut data, tileset_gid) {
+                        // A tile inside an XML encoded layer
+                        (Some(gids), _) => gids.push(parse_attr(path, attr("gid").or(Some("0")))?),
+                        (None, Some(gid)) => {
+                            let id: u32 = parse_attr(path, attr("id"))?;
+                            tile_gid = Some(gid + id);
+                        },
+                        (None, None) => (),
+                    },
+                    "property" => if let Some(gid) = tile_gid {
+                        if let (Some(name), Some(value)) = (attr("name"), attr("value")) {
+                            raw.properties.entry(gid).or_insert_with(HashMap::new)
+                                .insert(name.to_string(), value.to_string());
+                        }
+                    },
+                    "data" => {
+                        match attr("encoding") {
+                            None | Some("csv") => (),
+                            Some(_) => return Err(bad(path, "tile layers need to use CSV or XML encoding".to_string())),
+                        }
+                        if attr("compression").is_some() {
+                            return Err(bad(path, "tile layers can't be compressed".to_string()));
+                        }
+                        data = Some(Vec::new());
+                        csv.clear();
+                    },
+                    // Objects inside a tileset are collision shapes, not placements
+                    "object" if tileset_gid.is_none() => {
+                        let number = |key: &str| parse_attr::<f32>(path, attr(key).or(Some("0")));
+                        raw.objects.push(RawObject::new(
+                            attr("name").unwrap_or("").to_string(),
+                            attr("type").or(attr("class")).unwrap_or("").to_string(),
+                            (number("x")?, number("y")?, number("width")?, number("height")?),
+                            attr("gid").is_some(),
+                            raw.layers.len().saturating_sub(1),
+                        ));
+                    },
+                    _ => (),
+                }
+            },
+            XmlEvent::Characters(text) => if data.is_some() {
+                csv.push_str(&text);
+            },
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "tileset" => tileset_gid = None,
+                "tile" => tile_gid = None,
+                "data" => {
+                    let mut gids = data.take().unwrap_or_default();
+                    for gid in csv.split(',').map(str::trim).filter(|gid| !gid.is_empty()) {
+                        gids.push(parse_attr(path, Some(gid))?);
+                    }
+                    raw.layers.push(gids);
+                },
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn attribute<'a>(attributes: &'a [OwnedAttribute], key: &str) -> Option<&'a str> {
+    attributes.iter()
+        .find(|a| a.name.local_name == key)
+        .map(|a| a.value.as_str())
+}
+
+fn parse_attr<T: std::str::FromStr>(path: &Path, value: Option<&str>) -> Result<T, MapError> {
+    match value.map(str::parse) {
+        Some(Ok(value)) => Ok(value),
+        _ => Err(bad(path, format!("expected a number, found {:?}", value))),
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+/// Tiled stores other files relative to the one referring to them.
+fn sibling(path: &Path, relative: &str) -> PathBuf {
+    path.parent().unwrap_or_else(|| Path::new(".")).join(relative)
+}
+
+fn bad(path: &Path, reason: String) -> MapError {
+    MapError::BadTiled(path.to_path_buf(), reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES: &str = "./resources/maps/tiled";
+
+    fn legend() -> Legend {
+        Legend::load("./resources/maps/legend.ron").expect("The shared legend loads")
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(FIXTURES).join(name)
+    }
+
+    fn rows(level: &[&str]) -> Vec<Vec<char>> {
+        level.iter().map(|row| row.chars().collect()).collect()
+    }
+
+    #[test]
+    fn json_map_imports_its_layers_and_objects() {
+        let level = import(&fixture("small.json"), &legend()).expect("JSON map imports");
+
+        assert_eq!(level.levels, vec![
+            rows(&[
+                "######",
+                "#....#",
+                "#.==.#",
+                "######",
+            ]),
+            rows(&[
+                "######",
+                "#    #",
+                "#....#",
+                "######",
+            ]),
+        ]);
+        assert_eq!(level.meta.name, "small");
+        assert_eq!(level.meta.player_spawns, vec![MapPoint::new(1, 1, 0)]);
+        assert_eq!(level.meta.elevator, Some(MapPoint::new(4, 2, 0)));
+        assert_eq!(level.meta.enemy_spawners, vec![
+            EnemySpawner { skirmer: "Sniper".to_string(), point: MapPoint::new(4, 1, 0) },
+        ]);
+        assert_eq!(level.meta.items, vec![
+            ItemPickup { item: ".30-06 Rifle".to_string(), point: MapPoint::new(2, 2, 1) },
+        ]);
+    }
+
+    #[test]
+    fn json_and_tmx_import_the_same() {
+        let legend = legend();
+        let json = import(&fixture("small.json"), &legend).expect("JSON map imports");
+        let tmx = import(&fixture("small.tmx"), &legend).expect("TMX map imports");
+        assert_eq!(json.levels, tmx.levels);
+        assert_eq!(json.meta, tmx.meta);
+    }
+
+    #[test]
+    fn external_tilesets_resolve() {
+        let legend = legend();
+        let embedded = import(&fixture("small.json"), &legend).expect("JSON map imports");
+        let mut external = import(&fixture("small_external.json"), &legend).expect("External tileset resolves");
+        external.meta.name = embedded.meta.name.clone();
+        assert_eq!(embedded.levels, external.levels);
+        assert_eq!(embedded.meta, external.meta);
+    }
+
+    #[test]
+    fn tile_without_a_glyph_is_an_error() {
+        let path = fixture("no_glyph.json");
+        let err = import(&path, &legend()).err();
+        assert_eq!(err, Some(bad(&path, "tile 3 has no glyph or tile_type property".to_string())));
+    }
+
+    #[test]
+    fn missing_tileset_is_an_error() {
+        let path = fixture("small.tmx");
+        let mut raw = RawMap::default();
+        match read_tileset(&sibling(&path, "missing.tsx"), 1, &mut raw) {
+            Err(MapError::Io(missing, _)) => assert_eq!(missing, fixture("missing.tsx")),
+            other => panic!("Expected an IO error, got {:?}", other),
+        }
+    }
+}
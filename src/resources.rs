@@ -6,6 +6,53 @@ pub struct DeltaTime {
 
 impl DeltaTime {
     pub fn as_dt(&self) -> f32 {
-        self.delta.as_secs() as f32 + self.delta.subsec_nanos() as f32 * 1e-9
+        duration_secs(&self.delta)
+    }
+}
+
+/// How far drawing is between the last two simulation ticks, from 0 at the
+/// one before to 1 at the latest.
+pub struct Interpolation {
+    pub alpha: f32,
+}
+
+pub fn duration_secs(duration: &Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 * 1e-9
+}
+
+/// Frame time waiting to be simulated, handed out a fixed tick at a time so
+/// the simulation plays out the same at any frame rate.
+pub struct FixedTimestep {
+    pub tick: Duration,
+    // Ticks one slow frame can catch up on, past that the game slows down
+    // instead
+    max_ticks: u32,
+    // Always less than a tick after advancing
+    unsimulated: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(tick: Duration, max_ticks: u32) -> Self {
+        Self {
+            tick,
+            max_ticks,
+            unsimulated: Duration::new(0, 0),
+        }
+    }
+
+    /// Adds a frame's time, returning how many ticks are due.
+    pub fn advance(&mut self, frame: Duration) -> u32 {
+        self.unsimulated = (self.unsimulated + frame).min(self.tick * self.max_ticks);
+        let mut ticks = 0;
+        while self.unsimulated >= self.tick {
+            self.unsimulated -= self.tick;
+            ticks += 1;
+        }
+        ticks
+    }
+
+    /// How far drawing is past the latest tick, for `Interpolation`.
+    pub fn alpha(&self) -> f32 {
+        duration_secs(&self.unsimulated) / duration_secs(&self.tick)
     }
 }
@@ -3,27 +3,115 @@ use ncollide2d::{
     world::{CollisionGroups, GeometricQueryType},
 };
 
-use nalgebra::Isometry2;
 use specs::{Entity, World};
+use ggez::GameError;
+use ron;
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    path::Path,
+};
 
 use crate::{
-    Vector2, CollisionWorld,
+    SkirmResult, Vector2, CollisionWorld,
     game::PLAYER_COLLISION_GROUP,
     item::{Weapon, Item, ItemFactory},
     components::*,
     map::{SkirmMap, MapPoint, MapError},
+    systems::MOVE_SPEED,
 };
 
+pub const SKIRMERS_FILE: &str = "./resources/skirmers.ron";
+
 pub enum SkirmerType {
     Fighter,
     Sniper,
 }
 
-pub struct SkirmerFactory;
+impl SkirmerType {
+    /// Looks a skirmer up by its name in "skirmers.ron".
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Fighter" => Some(SkirmerType::Fighter),
+            "Sniper" => Some(SkirmerType::Sniper),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SkirmerType::Fighter => "Fighter",
+            SkirmerType::Sniper => "Sniper",
+        }
+    }
+}
+
+/// A skirmer's entry in "skirmers.ron". Only the parts creating one uses
+/// are read.
+#[derive(Clone, Deserialize)]
+pub struct SkirmerDef {
+    // Named after its entry in "weapons.ron"
+    #[serde(default)]
+    pub weapon: String,
+    #[serde(default)]
+    pub jump: JumpParams,
+    // Pixels per second when walking
+    #[serde(default = "default_run_speed")]
+    pub run_speed: f32,
+    // Heavy skirmers hit harder and get knocked back less
+    #[serde(default = "default_mass")]
+    pub mass: f32,
+    #[serde(default)]
+    pub max_health: u8,
+    // Strong skirmers smash through secret walls by walking into them
+    #[serde(default)]
+    pub strength: u8,
+    // How well it aims
+    #[serde(default)]
+    pub dexterity: u8,
+}
+
+fn default_mass() -> f32 {
+    1.0
+}
+
+fn default_run_speed() -> f32 {
+    MOVE_SPEED
+}
+
+#[derive(Clone)]
+pub struct SkirmerFactory {
+    skirmers: HashMap<String, SkirmerDef>,
+}
 
 impl SkirmerFactory {
-    pub fn new() -> Self {
-        Self {  }
+    pub fn new() -> SkirmResult<Self> {
+        Self::load(SKIRMERS_FILE)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> SkirmResult<Self> {
+        let mut skirmer_file = File::open(path)?;
+        let mut buffer = String::new();
+        skirmer_file.read_to_string(&mut buffer)?;
+        let skirmers: HashMap<String, SkirmerDef> = match ron::de::from_str(buffer.as_str()) {
+            Ok(result) => result,
+            Err(e) => {
+                let msg = format!("Error reading skirmers.ron, format is corrupt. {:?}", e);
+                return Err(GameError::ResourceLoadError(msg));
+            },
+        };
+
+        // Knockback divides by mass, so it has to be there
+        for (name, def) in &skirmers {
+            if def.mass.is_nan() || def.mass <= 0.0 {
+                let msg = format!("Error reading skirmers.ron, {} has mass {}, it needs to be more than 0", name, def.mass);
+                return Err(GameError::ResourceLoadError(msg));
+            }
+        }
+
+        Ok(SkirmerFactory { skirmers })
     }
 
     pub fn create_skirmer(
@@ -37,16 +125,29 @@ impl SkirmerFactory {
     ) -> Result<Entity, MapError> {
         // let (weapon, items) = self.get_skirmer_items(skirmer, item_factory);
 
-        let tile_point = MapPoint::new(tile_x, tile_y);
+        let tile_point = MapPoint::new(tile_x, tile_y, 0);
+        if !map.in_bounds(&tile_point) {
+            return Err(MapError::PointDoesNotExist);
+        }
         let (x, y) = tile_point.as_float_coord_tuple();
+        let (jump, mass, stats) = match self.skirmers.get(skirmer.name()) {
+            Some(def) => (def.jump.clone(), def.mass, StatsComp::new(def.max_health, def.strength, def.dexterity, def.run_speed)),
+            None => {
+                warn!("{:?} isn't in skirmers.ron, using the default jump, mass and stats", skirmer.name());
+                (JumpParams::default(), default_mass(), StatsComp::default())
+            },
+        };
+        let mut physics = PhysicsComp::new(PhysicsType::Moveable, nalgebra::zero());
+        physics.mass = mass;
 
         let ent = world.create_entity()
             .with(PositionComp::new(x, y))
             .with(AnimComp::new(String::from("default"), true))
             .with(SpriteComp::new(String::from("green_box")))
-            .with(StatsComp::default())
+            .with(stats)
             .with(StateComp::new())
-            .with(PhysicsComp::new(PhysicsType::Moveable, nalgebra::zero()))
+            .with(physics)
+            .with(JumpComp::new(jump))
             .build();
 
         // Player collision info
@@ -59,7 +160,7 @@ impl SkirmerFactory {
         let collider = {
             let mut collide_world = world.write_resource::<CollisionWorld>();
             let player_handle = collide_world.add(
-                Isometry2::new(Vector2::new(0.0, -6.0), nalgebra::zero()),
+                CollideComp::isometry_at(x, y),
                 ShapeHandle::new(shape.clone()),
                 group,
                 query_type,
@@ -72,6 +173,15 @@ impl SkirmerFactory {
         };
 
         world.write::<CollideComp>().insert(ent, collider);
+
+        let weapon_name = self.skirmers.get(skirmer.name()).map_or("", |def| def.weapon.as_str());
+        match item_factory.find_weapon(weapon_name) {
+            Some(weapon) => {
+                world.write::<EquipmentComp>().insert(ent, EquipmentComp::new(weapon, vec![]));
+            },
+            None => warn!("{:?} has no weapon {:?}, it won't be able to shoot", skirmer.name(), weapon_name),
+        }
+
         map.add_occupant(ent, tile_point).map(|()| ent)
     }
 
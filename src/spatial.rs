@@ -0,0 +1,263 @@
+use ggez::graphics::Rect;
+use ncollide2d::world::CollisionGroups;
+use specs::Entity;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{Point2, Vector2};
+
+/// An entity found by a `SpatialQuery`, and how far it is in pixels from the
+/// query's center or from the start of its ray.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SpatialHit {
+    pub ent: Entity,
+    pub distance: f32,
+}
+
+#[derive(Clone)]
+struct SpatialBody {
+    mins: Point2,
+    maxs: Point2,
+    groups: CollisionGroups,
+}
+
+impl SpatialBody {
+    fn in_groups(&self, groups: &[usize]) -> bool {
+        groups.is_empty() || groups.iter().any(|group| self.groups.is_member_of(*group))
+    }
+
+    /// Distance from `point` to the nearest edge of the body, or 0 inside it.
+    fn distance_to(&self, point: &Point2) -> f32 {
+        let dx = (self.mins.x - point.x).max(point.x - self.maxs.x).max(0.0);
+        let dy = (self.mins.y - point.y).max(point.y - self.maxs.y).max(0.0);
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /// How far along `dir` the ray first touches the body, slab by slab.
+    fn ray_distance(&self, origin: &Point2, dir: &Vector2) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = std::f32::INFINITY;
+        for (o, d, min, max) in &[
+            (origin.x, dir.x, self.mins.x, self.maxs.x),
+            (origin.y, dir.y, self.mins.y, self.maxs.y),
+        ] {
+            if d.abs() < std::f32::EPSILON {
+                if o < min || o > max {
+                    return None;
+                }
+            } else {
+                let t1 = (min - o) / d;
+                let t2 = (max - o) / d;
+                near = near.max(t1.min(t2));
+                far = far.min(t1.max(t2));
+            }
+        }
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+}
+
+/// A spatial hash over every entity with a collider, rebuilt each tick by
+/// `SpatialSys`. Queries take collision groups to look for, or an empty slice
+/// for any group, and return the closest hits first.
+pub struct SpatialQuery {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<Entity>>,
+    bodies: HashMap<Entity, SpatialBody>,
+}
+
+impl SpatialQuery {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            bodies: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.bodies.clear();
+    }
+
+    /// Adds an entity covering the box from `mins` to `maxs` in pixels.
+    pub fn insert(&mut self, ent: Entity, mins: Point2, maxs: Point2, groups: CollisionGroups) {
+        for cell in self.cells_between(&mins, &maxs) {
+            self.cells.entry(cell).or_insert_with(Vec::new).push(ent);
+        }
+        self.bodies.insert(ent, SpatialBody { mins, maxs, groups });
+    }
+
+    /// Everything within `radius` pixels of `center`.
+    pub fn within_radius(&self, center: Point2, radius: f32, groups: &[usize]) -> Vec<SpatialHit> {
+        let mins = Point2::new(center.x - radius, center.y - radius);
+        let maxs = Point2::new(center.x + radius, center.y + radius);
+        let hits = self.candidates(&mins, &maxs, groups).into_iter()
+            .map(|(ent, body)| SpatialHit { ent, distance: body.distance_to(&center) })
+            .filter(|hit| hit.distance <= radius)
+            .collect();
+        sorted(hits)
+    }
+
+    /// Everything overlapping `rect`, measured from its center.
+    pub fn within_rect(&self, rect: Rect, groups: &[usize]) -> Vec<SpatialHit> {
+        let mins = Point2::new(rect.x, rect.y);
+        let maxs = Point2::new(rect.x + rect.w, rect.y + rect.h);
+        let center = Point2::new(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0);
+        let hits = self.candidates(&mins, &maxs, groups).into_iter()
+            .filter(|(_, body)| body.mins.x <= maxs.x && body.maxs.x >= mins.x && body.mins.y <= maxs.y && body.maxs.y >= mins.y)
+            .map(|(ent, body)| SpatialHit { ent, distance: body.distance_to(&center) })
+            .collect();
+        sorted(hits)
+    }
+
+    /// Everything the ray from `origin` along `dir` passes through within
+    /// `max_distance` pixels, in the order it reaches them.
+    pub fn cast_ray(&self, origin: Point2, dir: Vector2, max_distance: f32, groups: &[usize]) -> Vec<SpatialHit> {
+        let length = dir.norm();
+        if length < std::f32::EPSILON {
+            return Vec::new();
+        }
+        let dir = dir / length;
+        let end = origin + dir * max_distance;
+        let mins = Point2::new(origin.x.min(end.x), origin.y.min(end.y));
+        let maxs = Point2::new(origin.x.max(end.x), origin.y.max(end.y));
+
+        let hits = self.candidates(&mins, &maxs, groups).into_iter()
+            .filter_map(|(ent, body)| body.ray_distance(&origin, &dir).map(|distance| SpatialHit { ent, distance }))
+            .filter(|hit| hit.distance <= max_distance)
+            .collect();
+        sorted(hits)
+    }
+
+    /// Every body in the cells touching the box, once each.
+    fn candidates(&self, mins: &Point2, maxs: &Point2, groups: &[usize]) -> Vec<(Entity, &SpatialBody)> {
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        for cell in self.cells_between(mins, maxs) {
+            for ent in self.cells.get(&cell).into_iter().flatten() {
+                if !seen.insert(*ent) {
+                    continue;
+                }
+                if let Some(body) = self.bodies.get(ent).filter(|body| body.in_groups(groups)) {
+                    found.push((*ent, body));
+                }
+            }
+        }
+        found
+    }
+
+    fn cells_between(&self, mins: &Point2, maxs: &Point2) -> Vec<(i32, i32)> {
+        let cell = |v: f32| (v / self.cell_size).floor() as i32;
+        let mut cells = Vec::new();
+        for j in cell(mins.y)..=cell(maxs.y) {
+            for i in cell(mins.x)..=cell(maxs.x) {
+                cells.push((i, j));
+            }
+        }
+        cells
+    }
+}
+
+fn sorted(mut hits: Vec<SpatialHit>) -> Vec<SpatialHit> {
+    hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal));
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::World;
+
+    use super::*;
+
+    fn groups(group: usize) -> CollisionGroups {
+        let mut groups = CollisionGroups::new();
+        groups.set_membership(&[group]);
+        groups
+    }
+
+    /// A 10 pixel square body with its top-left corner at `x`, `y`.
+    fn insert_box(query: &mut SpatialQuery, world: &mut World, x: f32, y: f32, group: usize) -> Entity {
+        let ent = world.create_entity().build();
+        query.insert(ent, Point2::new(x, y), Point2::new(x + 10.0, y + 10.0), groups(group));
+        ent
+    }
+
+    fn ents(hits: &[SpatialHit]) -> Vec<Entity> {
+        hits.iter().map(|hit| hit.ent).collect()
+    }
+
+    #[test]
+    fn radius_hits_come_closest_first() {
+        let mut world = World::new();
+        let mut query = SpatialQuery::new(32.0);
+        let far = insert_box(&mut query, &mut world, 60.0, 0.0, 1);
+        let near = insert_box(&mut query, &mut world, 20.0, 0.0, 1);
+        let outside = insert_box(&mut query, &mut world, 200.0, 0.0, 1);
+
+        let hits = query.within_radius(Point2::new(0.0, 5.0), 100.0, &[]);
+        assert_eq!(ents(&hits), vec![near, far]);
+        assert_eq!(hits[0].distance, 20.0);
+        assert!(!ents(&hits).contains(&outside));
+    }
+
+    #[test]
+    fn queries_only_return_the_groups_asked_for() {
+        let mut world = World::new();
+        let mut query = SpatialQuery::new(32.0);
+        let player = insert_box(&mut query, &mut world, 10.0, 0.0, 1);
+        let tile = insert_box(&mut query, &mut world, 30.0, 0.0, 2);
+
+        let center = Point2::new(0.0, 5.0);
+        assert_eq!(ents(&query.within_radius(center, 100.0, &[1])), vec![player]);
+        assert_eq!(ents(&query.within_radius(center, 100.0, &[2])), vec![tile]);
+        assert_eq!(ents(&query.within_radius(center, 100.0, &[])), vec![player, tile]);
+        assert!(query.within_radius(center, 100.0, &[3]).is_empty());
+    }
+
+    #[test]
+    fn rect_hits_overlap_the_rect() {
+        let mut world = World::new();
+        let mut query = SpatialQuery::new(32.0);
+        let inside = insert_box(&mut query, &mut world, 40.0, 40.0, 1);
+        let touching = insert_box(&mut query, &mut world, 95.0, 50.0, 1);
+        insert_box(&mut query, &mut world, 150.0, 50.0, 1);
+
+        let hits = query.within_rect(Rect::new(0.0, 0.0, 100.0, 100.0), &[]);
+        assert_eq!(ents(&hits), vec![inside, touching]);
+    }
+
+    #[test]
+    fn rays_hit_things_in_the_order_they_reach_them() {
+        let mut world = World::new();
+        let mut query = SpatialQuery::new(32.0);
+        let second = insert_box(&mut query, &mut world, 100.0, 0.0, 1);
+        let first = insert_box(&mut query, &mut world, 40.0, 0.0, 1);
+        let beyond = insert_box(&mut query, &mut world, 300.0, 0.0, 1);
+        let behind = insert_box(&mut query, &mut world, -50.0, 0.0, 1);
+        let aside = insert_box(&mut query, &mut world, 60.0, 50.0, 1);
+
+        let origin = Point2::new(0.0, 5.0);
+        let hits = query.cast_ray(origin, Vector2::new(2.0, 0.0), 200.0, &[]);
+        assert_eq!(ents(&hits), vec![first, second]);
+        assert_eq!(hits[0].distance, 40.0);
+        assert_eq!(hits[1].distance, 100.0);
+        for missed in &[beyond, behind, aside] {
+            assert!(!ents(&hits).contains(missed));
+        }
+    }
+
+    #[test]
+    fn rays_skip_other_groups() {
+        let mut world = World::new();
+        let mut query = SpatialQuery::new(32.0);
+        insert_box(&mut query, &mut world, 40.0, 0.0, 2);
+        let target = insert_box(&mut query, &mut world, 100.0, 0.0, 1);
+
+        let hits = query.cast_ray(Point2::new(0.0, 5.0), Vector2::new(1.0, 0.0), 200.0, &[1]);
+        assert_eq!(ents(&hits), vec![target]);
+    }
+}
@@ -5,13 +5,35 @@ mod state;
 mod render;
 mod sound;
 mod physics;
+mod terrain;
+mod vision;
+mod elevator;
+mod secret;
+mod occupancy;
+mod spatial;
+mod autotile;
+mod combat;
+mod projectile;
+mod nav;
+mod pickup;
 
 pub use self::{
-    state::StateSys,
+    state::{StateSys, MOVE_SPEED},
     plan::PlanSys,
     position::PositionSys,
     render::{RenderSys, AnimSys},
     sound::SoundSys,
     stats::StatsSys,
-    physics::PhysicsSys,
+    physics::{PhysicsSys, GRAVITY},
+    terrain::TerrainSys,
+    vision::VisionSys,
+    elevator::ElevatorSys,
+    secret::SecretSys,
+    occupancy::OccupancySys,
+    spatial::SpatialSys,
+    autotile::AutotileSys,
+    combat::CombatSys,
+    projectile::{FireSys, ProjectileSys},
+    nav::NavSys,
+    pickup::PickupSys,
 };
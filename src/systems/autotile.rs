@@ -0,0 +1,34 @@
+use specs::{Fetch, System, WriteStorage};
+
+use std::collections::HashSet;
+
+use crate::{
+    components::*,
+    map::{AutotileRules, SkirmMap, TerrainEvents},
+};
+
+// Picks new sprites for every tile changed this tick and the tiles around it,
+// since their bitmasks change along with it
+pub struct AutotileSys;
+impl<'a> System<'a> for AutotileSys {
+    type SystemData = (
+        Fetch<'a, SkirmMap>,
+        Fetch<'a, AutotileRules>,
+        Fetch<'a, TerrainEvents>,
+        WriteStorage<'a, SpriteComp>,
+    );
+
+    fn run(&mut self, (map, rules, events, mut sprites): Self::SystemData) {
+        info!("<- AutotileSys");
+        let mut points = HashSet::new();
+        for event in &events.events {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    points.insert(event.point.offset(dx, dy));
+                }
+            }
+        }
+        map.retile(points, &rules, &mut sprites);
+        info!("-> AutotileSys");
+    }
+}
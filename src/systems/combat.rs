@@ -0,0 +1,70 @@
+use specs::{Entity, FetchMut, ReadStorage, System, WriteStorage};
+
+use crate::{
+    Vector2,
+    combat::HitEvents,
+    components::*,
+};
+
+// Pixels per second a hit from an attacker of mass 1 gives a target of mass 1,
+// sideways away from the attacker and up off the ground
+const KNOCKBACK_SPEED: f32 = 160.0;
+const KNOCKBACK_LIFT: f32 = 120.0;
+
+// Seconds a hit target can't act for
+const HIT_STUN_TIME: f32 = 0.3;
+
+// Applies the damage and knockback of every hit queued up since last tick
+pub struct CombatSys;
+impl<'a> System<'a> for CombatSys {
+    type SystemData = (
+        FetchMut<'a, HitEvents>,
+        WriteStorage<'a, StatsComp>,
+        WriteStorage<'a, StateComp>,
+        WriteStorage<'a, PhysicsComp>,
+        ReadStorage<'a, PositionComp>,
+    );
+
+    fn run(&mut self, (mut hits, mut stats, mut state, mut physics, pos): Self::SystemData) {
+        info!("<- CombatSys");
+        for hit in hits.hits.drain(..) {
+            if let Some(s) = stats.get_mut(hit.target) {
+                s.health = s.health.saturating_sub(hit.damage);
+            }
+
+            let attacker_mass = hit.attacker
+                .and_then(|attacker| physics.get(attacker))
+                .map_or(1.0, |p| p.mass);
+            let side = knockback_side(hit.direction.x, hit.attacker, hit.target, &pos);
+            let push = Vector2::new(side * KNOCKBACK_SPEED, -KNOCKBACK_LIFT) * attacker_mass;
+            if let Some(p) = physics.get_mut(hit.target) {
+                // Knocked out of whatever it was doing, falling included
+                p.velocity = Vector2::new(0.0, 0.0);
+                p.apply_impulse(push);
+            }
+
+            if let Some(s) = state.get_mut(hit.target) {
+                s.hit_stun = HIT_STUN_TIME;
+                s.climbing = false;
+                s.on_ground = false;
+                s.on_surface = false;
+            }
+        }
+        info!("-> CombatSys");
+    }
+}
+
+/// Which way a hit pushes its target sideways: along the hit, or away from
+/// the attacker for hits going straight up or down. A target right on top of
+/// its attacker only gets lifted.
+fn knockback_side(direction_x: f32, attacker: Option<Entity>, target: Entity, pos: &ReadStorage<PositionComp>) -> f32 {
+    if direction_x != 0.0 {
+        return direction_x.signum();
+    }
+
+    let away = match (attacker.and_then(|attacker| pos.get(attacker)), pos.get(target)) {
+        (Some(from), Some(to)) => to.x - from.x,
+        _ => 0.0,
+    };
+    if away == 0.0 { 0.0 } else { away.signum() }
+}
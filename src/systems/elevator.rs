@@ -0,0 +1,70 @@
+use specs::{Fetch, FetchMut, System, ReadStorage, WriteStorage, Join};
+
+use crate::{
+    components::*,
+    input::PlayerInputState,
+    level::LevelSequence,
+    map::{MapPoint, TILE_WIDTH, TILE_HEIGHT},
+    resources::DeltaTime,
+};
+
+// Seconds between calling the elevator and it starting its arrival
+const CALL_WAIT: f32 = 3.0;
+const ARRIVE_TIME: f32 = 2.0;
+const DESCEND_TIME: f32 = 2.0;
+
+// Runs the elevator state machine. The player calls it by pressing down next
+// to it, waits, then boards by pressing down while standing in it.
+pub struct ElevatorSys;
+impl<'a> System<'a> for ElevatorSys {
+    type SystemData = (
+        Fetch<'a, DeltaTime>,
+        Fetch<'a, PlayerInputState>,
+        FetchMut<'a, LevelSequence>,
+        ReadStorage<'a, PositionComp>,
+        WriteStorage<'a, ElevatorComp>,
+    );
+
+    fn run(&mut self, (time, player_input, mut levels, position, mut elevators): Self::SystemData) {
+        info!("<- ElevatorSys");
+        let dt = time.as_dt();
+        let pressing_down = player_input.input.down.state;
+        let player_tile = position.get(player_input.ent)
+            .map(|p| MapPoint::from_pixel_coord(p.x as i32 + TILE_WIDTH / 2, p.y as i32 + TILE_HEIGHT / 2, 0));
+
+        for e in (&mut elevators).join() {
+            e.timer += dt;
+            let on = player_tile == Some(e.point);
+            let near = player_tile.map_or(false, |p| (p.x - e.point.x).abs() <= 1 && (p.y - e.point.y).abs() <= 1);
+
+            match e.state {
+                ElevatorState::Idle => {
+                    if near && pressing_down {
+                        e.change_state(ElevatorState::Called);
+                    }
+                },
+                ElevatorState::Called => {
+                    if e.timer >= CALL_WAIT {
+                        e.change_state(ElevatorState::Arriving);
+                    }
+                },
+                ElevatorState::Arriving => {
+                    if e.timer >= ARRIVE_TIME {
+                        e.change_state(ElevatorState::Boarding);
+                    }
+                },
+                ElevatorState::Boarding => {
+                    if on && pressing_down {
+                        e.change_state(ElevatorState::Descending);
+                    }
+                },
+                ElevatorState::Descending => {
+                    if e.timer >= DESCEND_TIME {
+                        levels.descend = true;
+                    }
+                },
+            }
+        }
+        info!("-> ElevatorSys");
+    }
+}
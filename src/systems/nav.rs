@@ -0,0 +1,95 @@
+use specs::{Fetch, FetchMut, System, ReadStorage, WriteStorage, Join};
+
+use crate::{
+    components::*,
+    input::{InputState, PlayerInputState},
+    map::{SkirmMap, MapPoint, NavAction, NavGraph, NavGraphs, TerrainEvents, TileGrid, TILE_WIDTH, TILE_HEIGHT},
+    resources::DeltaTime,
+};
+
+// Seconds between planning routes again, to keep up with the player
+const REPLAN_TIME: f32 = 0.5;
+
+// Walks skirmers with a `NavComp` along their routes towards the player by
+// holding the keys a player would. Runs after the terrain has changed, so the
+// keys are acted on next tick.
+pub struct NavSys;
+impl<'a> System<'a> for NavSys {
+    type SystemData = (
+        Fetch<'a, DeltaTime>,
+        Fetch<'a, SkirmMap>,
+        Fetch<'a, PlayerInputState>,
+        Fetch<'a, TerrainEvents>,
+        FetchMut<'a, NavGraphs>,
+        ReadStorage<'a, PositionComp>,
+        ReadStorage<'a, JumpComp>,
+        WriteStorage<'a, StateComp>,
+        WriteStorage<'a, NavComp>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("<- NavSys");
+        let (time, map, player_input, terrain_events, mut graphs, position, jump, mut state, mut nav) = data;
+        let dt = time.as_dt();
+
+        if !terrain_events.events.is_empty() {
+            graphs.clear();
+        }
+        let target = position.get(player_input.ent).map(center_tile);
+
+        for (p, j, s, n) in (&position, &jump, &mut state, &mut nav).join() {
+            let here = center_tile(p);
+
+            n.since_plan += dt;
+            if n.since_plan >= REPLAN_TIME {
+                n.since_plan = 0.0;
+                n.path = match target {
+                    Some(target) => plan_route(graphs.graph(&map, &n.profile), &map.map, &here, &target),
+                    None => Vec::new(),
+                };
+            }
+
+            while n.path.first().map_or(false, |action| action.target() == here) {
+                n.path.remove(0);
+            }
+            s.move_action = steer(n.path.first(), &here, s, j);
+        }
+        info!("-> NavSys");
+    }
+}
+
+fn center_tile(p: &PositionComp) -> MapPoint {
+    MapPoint::from_pixel_coord(p.x as i32 + TILE_WIDTH / 2, p.y as i32 + TILE_HEIGHT / 2, 0)
+}
+
+/// The route between the ground under `from` and the ground under `to`, or
+/// nothing if there isn't one.
+fn plan_route(graph: &NavGraph, map: &TileGrid, from: &MapPoint, to: &MapPoint) -> Vec<NavAction> {
+    let start = graph.ground_node(map, from);
+    let goal = graph.ground_node(map, to);
+    match (start, goal) {
+        (Some(start), Some(goal)) => graph.pathfind(&start, &goal).unwrap_or_else(Vec::new),
+        _ => Vec::new(),
+    }
+}
+
+/// The keys to hold this tick to carry out `action` from `here`.
+fn steer(action: Option<&NavAction>, here: &MapPoint, state: &StateComp, jump: &JumpComp) -> InputState {
+    let mut input = InputState::new();
+    let action = match action {
+        Some(action) => action,
+        None => return input,
+    };
+
+    let dx = action.target().x - here.x;
+    input.left.set(dx < 0);
+    input.right.set(dx > 0);
+
+    // Jumps start on a fresh press and only reach their full height while
+    // it's held, so let go once landed to press again
+    if let NavAction::Jump { .. } = action {
+        let pressing = if state.is_on_ground() { !jump.held } else { jump.rising };
+        input.up.set(pressing);
+    }
+    input
+}
@@ -0,0 +1,29 @@
+use specs::{Entities, FetchMut, System, ReadStorage, Join};
+
+use crate::{
+    components::*,
+    map::SkirmMap,
+};
+
+// Rebuilds which tiles each moving entity occupies from where it is now, so
+// moved and deleted entities never leave stale occupants behind. Shots in
+// flight don't occupy anything.
+pub struct OccupancySys;
+impl<'a> System<'a> for OccupancySys {
+    type SystemData = (
+        Entities<'a>,
+        FetchMut<'a, SkirmMap>,
+        ReadStorage<'a, PositionComp>,
+        ReadStorage<'a, PhysicsComp>,
+        ReadStorage<'a, ProjectileComp>,
+    );
+
+    fn run(&mut self, (entities, mut map, position, physics, projectile): Self::SystemData) {
+        info!("<- OccupancySys");
+        map.clear_occupants();
+        for (e, p, _, _) in (&*entities, &position, &physics, !&projectile).join() {
+            map.occupy(e, p.x, p.y);
+        }
+        info!("-> OccupancySys");
+    }
+}
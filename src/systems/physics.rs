@@ -1,60 +1,393 @@
-use specs::{Fetch, FetchMut, System, WriteStorage, ReadStorage, Join};
-use nalgebra::Translation;
-use ggez::graphics;
+use specs::{Fetch, FetchMut, System, WriteStorage, Join};
 
 use crate::{
     CollisionWorld,
     Vector2,
     Point2,
     resources::DeltaTime,
-    components::*
+    components::*,
+    map::{SkirmMap, MapPoint, TileType, TILE_WIDTH, TILE_HEIGHT},
 };
 
+// How far feet can sink into or hover over a slope and still stick to it
+const SURFACE_STEP: f32 = 8.0;
+
+// Seconds spent falling through platforms after pressing down on one
+const DROP_TIME: f32 = 0.25;
+
+// Pixels per second up or down a ladder
+const CLIMB_SPEED: f32 = 80.0;
+
+// Pixels per second per second
+pub const GRAVITY: f32 = 600.0;
+
+// The box bodies collide with tiles by, centered under the sprite and as wide
+// as the skirmers' colliders
+const BODY_HALF_WIDTH: f32 = 12.0;
+const BODY_HEIGHT: f32 = TILE_HEIGHT as f32;
+
+// Edges closer than this to a tile only touch it, they don't overlap it
+const SKIN: f32 = 0.01;
+
+// How high a ledge can be walked up onto, enough to get from the top of a
+// slope onto the wall it leans against
+const STEP_HEIGHT: f32 = 12.0;
+
 pub struct PhysicsSys;
 impl<'a> System<'a> for PhysicsSys{
     type SystemData = (
         WriteStorage<'a, PositionComp>,
         WriteStorage<'a, PhysicsComp>,
         WriteStorage<'a, CollideComp>,
-        ReadStorage<'a, StateComp>,
+        WriteStorage<'a, StateComp>,
         FetchMut<'a, CollisionWorld>,
+        Fetch<'a, SkirmMap>,
         Fetch<'a, DeltaTime>,
     );
 
-    fn run(&mut self, (mut pos, mut physics, mut collide, state, mut world, time): Self::SystemData) {
+    fn run(&mut self, (mut pos, mut physics, mut collide, mut state, mut world, map, time): Self::SystemData) {
         let dt = time.as_dt();
-        for (pos, p, s, c) in (&mut pos, &mut physics, &state, &mut collide).join() {
-            // Apply gravity
-            if !s.is_on_ground() {
-                p.acceleration += Vector2::new(0.0, 10.0 * dt);
+        for (pos, p, s, c) in (&mut pos, &mut physics, &mut state, &mut collide).join() {
+            s.drop_timer = (s.drop_timer - dt).max(0.0);
+            if s.on_surface && s.move_action.down.state {
+                s.drop_timer = DROP_TIME;
+            }
+            grab_climbable(pos, s, &map);
+
+            if s.climbing {
+                // Up and down move along the ladder instead of fighting gravity
+                let direction = if s.move_action.up.state {
+                    -1.0
+                } else if s.move_action.down.state {
+                    1.0
+                } else {
+                    0.0
+                };
+                p.velocity = Vector2::new(0.0, direction * CLIMB_SPEED);
+                p.acceleration = Vector2::new(0.0, 0.0);
+            } else {
+                // Apply gravity, even on the ground so resting bodies keep
+                // pressing into the floor
+                p.acceleration += Vector2::new(0.0, GRAVITY);
             }
 
-            // Update pos
-            p.velocity += p.acceleration;
+            // Update pos, one axis at a time so sliding along walls and floors
+            // still works
+            p.velocity += p.acceleration * dt;
             p.acceleration = Vector2::new(0.0, 0.0);
-            let new_position = {
-                let obj = world.collision_object(c.handle).unwrap();
-                let mut pos = obj.position().clone();
-                pos.append_translation_mut(&Translation::from_vector(p.velocity));
-                pos
-            };
-            world.set_position(c.handle, new_position);
-
-            let position = ggez_collision_object_pos(&world, c);
-            pos.x = position.x;
-            pos.y = position.y;
+            let start = CollideComp::sprite_position(world.collision_object(c.handle).unwrap().position());
+            let grounded = s.is_on_ground() && !s.climbing;
+            let (mut moved, contacts) = move_body(start, p.velocity * dt, grounded, &map);
+            if contacts.left || contacts.right {
+                p.velocity.x = 0.0;
+            }
+            if contacts.top || contacts.bottom {
+                p.velocity.y = 0.0;
+            }
+            p.contacts = contacts;
+            s.on_ground = contacts.bottom;
+
+            let old_foot = pos.y + TILE_HEIGHT as f32;
+            if s.climbing {
+                climb_off(s, p, &mut moved, old_foot, &map);
+            } else {
+                land_on_surface(s, p, &mut moved, old_foot, &map);
+            }
+            world.set_position(c.handle, CollideComp::isometry_at(moved.x, moved.y));
+
+            pos.x = moved.x;
+            pos.y = moved.y;
+        }
+    }
+}
+
+/// Moves a body whose sprite's top-left corner is at `start` by `delta`,
+/// first along x then along y, stopping at the first solid tile each way. A
+/// body on the ground steps up ledges up to `STEP_HEIGHT` high instead of
+/// stopping at them.
+fn move_body(start: Point2, delta: Vector2, grounded: bool, map: &SkirmMap) -> (Point2, Contacts) {
+    let mut contacts = Contacts::default();
+    let mut at = start;
+
+    let (dx, hit) = sweep(map, &at, 0, delta.x);
+    let stepped = if hit && grounded { step_up(map, &start, delta.x, dx) } else { None };
+    match stepped {
+        Some(stepped) => at = stepped,
+        None => {
+            at.x += dx;
+            contacts.left = hit && delta.x < 0.0;
+            contacts.right = hit && delta.x > 0.0;
+        },
+    }
+
+    let (dy, hit) = sweep(map, &at, 1, delta.y);
+    at.y += dy;
+    contacts.top = hit && delta.y < 0.0;
+    contacts.bottom = hit && delta.y > 0.0;
+
+    (at, contacts)
+}
+
+/// Lifts a body blocked `blocked_dx` pixels into a move of `dx`, moves it
+/// across, then lowers it back onto whatever it stepped onto. Nothing if
+/// there's no headroom, or if stepping up doesn't get any further.
+fn step_up(map: &SkirmMap, start: &Point2, dx: f32, blocked_dx: f32) -> Option<Point2> {
+    let (up, _) = sweep(map, start, 1, -STEP_HEIGHT);
+    let mut at = Point2::new(start.x, start.y + up);
+    let (lifted_dx, _) = sweep(map, &at, 0, dx);
+    if lifted_dx.abs() <= blocked_dx.abs() + SKIN {
+        return None;
+    }
+    at.x += lifted_dx;
+    let (down, _) = sweep(map, &at, 1, -up);
+    at.y += down;
+    Some(at)
+}
+
+/// How far the body at `at` can move `delta` pixels along `axis`, 0 for x and
+/// 1 for y, before running into a solid tile, and whether it did. The edge of
+/// the map counts as solid.
+fn sweep(map: &SkirmMap, at: &Point2, axis: usize, delta: f32) -> (f32, bool) {
+    let center_x = at.x + TILE_WIDTH as f32 / 2.0;
+    let mins = [center_x - BODY_HALF_WIDTH, at.y];
+    let maxs = [center_x + BODY_HALF_WIDTH, at.y + BODY_HEIGHT];
+    let size = [TILE_WIDTH as f32, TILE_HEIGHT as f32];
+    let other = 1 - axis;
+    let cell = |v: f32, axis: usize| (v / size[axis]).floor() as i32;
+    let is_solid = |along: i32| (cell(mins[other] + SKIN, other)..=cell(maxs[other] - SKIN, other)).any(|across| {
+        let point = if axis == 0 {
+            MapPoint::new(along, across, 0)
+        } else {
+            MapPoint::new(across, along, 0)
+        };
+        map.map.get(&point).map_or(true, |tile| tile.solid)
+    });
+
+    if delta > 0.0 {
+        let edge = maxs[axis];
+        for along in cell(edge, axis)..=cell(edge + delta, axis) {
+            let near = along as f32 * size[axis];
+            if near >= edge - SKIN && is_solid(along) {
+                return ((near - edge).max(0.0), true);
+            }
+        }
+    } else if delta < 0.0 {
+        let edge = mins[axis];
+        for along in (cell(edge + delta, axis)..=cell(edge, axis)).rev() {
+            let near = (along + 1) as f32 * size[axis];
+            if near <= edge + SKIN && is_solid(along) {
+                return ((near - edge).min(0.0), true);
+            }
         }
     }
+    (delta, false)
 }
 
-fn ggez_collision_object_pos(world: &CollisionWorld, collider: &CollideComp) -> graphics::Point2 {
-    let point = collision_object_pos(world, collider);
-    graphics::Point2::new(point.x, point.y)
+/// Platforms, slopes and ladder tops aren't in the collision world, so land on
+/// them here. Slopes catch feet from a step below so they can be walked up,
+/// the others only catch feet coming down from above.
+fn land_on_surface(s: &mut StateComp, p: &mut PhysicsComp, at: &mut Point2, old_foot: f32, map: &SkirmMap) {
+    let foot_x = at.x + TILE_WIDTH as f32 / 2.0;
+    let new_foot = at.y + TILE_HEIGHT as f32;
+    let stick = if s.on_surface { SURFACE_STEP } else { 0.0 };
+    s.on_surface = false;
+    if p.velocity.y < 0.0 {
+        return;
+    }
+
+    let slope = map.surface_between(
+        foot_x, old_foot.min(new_foot) - SURFACE_STEP, new_foot + stick,
+        |tile_type: &TileType| tile_type.slope().is_some(),
+    );
+    let platform = if s.drop_timer > 0.0 {
+        None
+    } else {
+        map.surface_between(
+            foot_x, old_foot, new_foot + stick,
+            |tile_type: &TileType| tile_type.is_platform() || tile_type.is_climbable(),
+        )
+    };
+    let surface = match (slope, platform) {
+        (Some(slope), Some(platform)) => Some(slope.min(platform)),
+        (slope, platform) => slope.or(platform),
+    };
+    if let Some(y) = surface {
+        at.y = y - TILE_HEIGHT as f32;
+        p.velocity.y = 0.0;
+        s.on_surface = true;
+    }
 }
 
-fn collision_object_pos(world: &CollisionWorld, collider: &CollideComp) -> Point2 {
-    let collision_object = world.collision_object(collider.handle).unwrap();
-    let isometry = collision_object.position();
-    let new_pos = Point2::new(isometry.translation.vector.x, isometry.translation.vector.y);
-    new_pos
+/// Grabs a ladder or vine behind the entity when pressing up or down, or the
+/// one under its feet when pressing down from on top of it.
+fn grab_climbable(pos: &PositionComp, s: &mut StateComp, map: &SkirmMap) {
+    if s.climbing || s.is_stunned() {
+        return;
+    }
+
+    let center_x = pos.x as i32 + TILE_WIDTH / 2;
+    let center = MapPoint::from_pixel_coord(center_x, pos.y as i32 + TILE_HEIGHT / 2, 0);
+    let under = MapPoint::from_pixel_coord(center_x, pos.y as i32 + TILE_HEIGHT + 1, 0);
+    let input = &s.move_action;
+    let behind = (input.up.state || input.down.state) && map.is_climbable(&center);
+    let below = input.down.state && map.is_climbable(&under);
+    if behind || below {
+        s.climbing = true;
+        s.on_surface = false;
+        // Let go of the ladder top to climb down through it
+        s.drop_timer = DROP_TIME;
+    }
+}
+
+/// Lets go of the ladder sideways, by stepping onto its top going up, onto
+/// the floor or a platform going down, or by falling off a bottom that ends
+/// in the air. Holds on to the middle of the ladder otherwise.
+fn climb_off(s: &mut StateComp, p: &mut PhysicsComp, at: &mut Point2, old_foot: f32, map: &SkirmMap) {
+    if s.is_moving_horizontal() || p.contacts.bottom {
+        s.climbing = false;
+        return;
+    }
+
+    let center_x = at.x + TILE_WIDTH as f32 / 2.0;
+    let new_foot = at.y + TILE_HEIGHT as f32;
+    let center = MapPoint::from_pixel_coord(center_x as i32, (new_foot - TILE_HEIGHT as f32 / 2.0) as i32, 0);
+    let column = center.x * TILE_WIDTH;
+    at.x = column as f32;
+
+    let landing = if p.velocity.y < 0.0 {
+        map.surface_between(center_x, new_foot, old_foot, |tile_type: &TileType| tile_type.is_climbable())
+    } else if p.velocity.y > 0.0 {
+        map.surface_between(center_x, old_foot, new_foot, |tile_type: &TileType| !tile_type.is_climbable())
+    } else {
+        None
+    };
+
+    if let Some(y) = landing {
+        at.y = y - TILE_HEIGHT as f32;
+        p.velocity.y = 0.0;
+        s.climbing = false;
+        s.on_surface = true;
+    } else if p.velocity.y > 0.0 && !map.is_climbable(&center) {
+        s.climbing = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ncollide2d::{
+        shape::{Cuboid, ShapeHandle},
+        world::{CollisionGroups, GeometricQueryType},
+    };
+    use specs::{Entity, RunNow, World};
+
+    use std::time::Duration;
+
+    use super::*;
+
+    fn room() -> SkirmMap {
+        SkirmMap::from_rows(&[
+            "#######",
+            "#.....#",
+            "#.....#",
+            "#...#.#",
+            "#######",
+        ])
+    }
+
+    #[test]
+    fn walls_stop_sideways_moves() {
+        let map = room();
+        let (at, contacts) = move_body(Point2::new(32.0, 64.0), Vector2::new(200.0, 0.0), false, &map);
+        assert_eq!(at, Point2::new(164.0, 64.0));
+        assert_eq!(contacts, Contacts { right: true, ..Contacts::default() });
+
+        let (at, contacts) = move_body(Point2::new(64.0, 64.0), Vector2::new(-200.0, 0.0), false, &map);
+        assert_eq!(at, Point2::new(28.0, 64.0));
+        assert_eq!(contacts, Contacts { left: true, ..Contacts::default() });
+
+        // A full height wall is too tall to step up, even on the ground
+        let (at, contacts) = move_body(Point2::new(160.0, 96.0), Vector2::new(20.0, 0.0), true, &map);
+        assert_eq!(at, Point2::new(164.0, 96.0));
+        assert!(contacts.right);
+    }
+
+    #[test]
+    fn floors_and_ceilings_stop_vertical_moves() {
+        let map = room();
+        let (at, contacts) = move_body(Point2::new(32.0, 64.0), Vector2::new(0.0, 200.0), false, &map);
+        assert_eq!(at, Point2::new(32.0, 96.0));
+        assert_eq!(contacts, Contacts { bottom: true, ..Contacts::default() });
+
+        let (at, contacts) = move_body(Point2::new(32.0, 64.0), Vector2::new(0.0, -200.0), false, &map);
+        assert_eq!(at, Point2::new(32.0, 32.0));
+        assert_eq!(contacts, Contacts { top: true, ..Contacts::default() });
+
+        // Open space all the way doesn't touch anything
+        let (at, contacts) = move_body(Point2::new(32.0, 64.0), Vector2::new(10.0, 10.0), false, &map);
+        assert_eq!(at, Point2::new(42.0, 74.0));
+        assert_eq!(contacts, Contacts::default());
+    }
+
+    #[test]
+    fn grounded_bodies_step_up_a_low_ledge() {
+        let map = room();
+        // Feet 8 pixels below the top of the ledge tile, as if coming off a slope
+        let start = Point2::new(96.0, 72.0);
+
+        let (at, contacts) = move_body(start, Vector2::new(20.0, 0.0), true, &map);
+        assert_eq!(at, Point2::new(116.0, 64.0));
+        assert_eq!(contacts, Contacts::default());
+
+        // In the air it just runs into the ledge
+        let (at, contacts) = move_body(start, Vector2::new(20.0, 0.0), false, &map);
+        assert_eq!(at, Point2::new(100.0, 72.0));
+        assert_eq!(contacts, Contacts { right: true, ..Contacts::default() });
+    }
+
+    fn add_body(world: &mut World, x: f32, y: f32, velocity: Vector2) -> Entity {
+        let ent = world.create_entity()
+            .with(PositionComp::new(x, y))
+            .with(PhysicsComp::new(PhysicsType::Moveable, velocity))
+            .with(StateComp::new())
+            .build();
+        let handle = world.write_resource::<CollisionWorld>().add(
+            CollideComp::isometry_at(x, y),
+            ShapeHandle::new(Cuboid::new(Vector2::new(12.0, 12.0))),
+            CollisionGroups::new(),
+            GeometricQueryType::Contacts(0.0, 0.0),
+            ent,
+        );
+        world.write::<CollideComp>().insert(ent, CollideComp { handle });
+        ent
+    }
+
+    #[test]
+    fn contacts_stop_velocity_along_their_normal() {
+        let mut world = World::new();
+        register_components(&mut world);
+        world.add_resource(CollisionWorld::new(0.02));
+        world.add_resource(room());
+        world.add_resource(DeltaTime { delta: Duration::new(0, 1_000_000_000 / 60) });
+
+        // Running into the right wall while standing on the floor
+        let runner = add_body(&mut world, 160.0, 96.0, Vector2::new(500.0, 0.0));
+        // Jumping into the ceiling
+        let jumper = add_body(&mut world, 32.0, 32.0, Vector2::new(0.0, -300.0));
+        PhysicsSys.run_now(&world.res);
+
+        let physics = world.read::<PhysicsComp>();
+        let state = world.read::<StateComp>();
+        let pos = world.read::<PositionComp>();
+
+        let runner_physics = physics.get(runner).unwrap();
+        assert_eq!(runner_physics.velocity, Vector2::new(0.0, 0.0));
+        assert_eq!(runner_physics.contacts, Contacts { right: true, bottom: true, ..Contacts::default() });
+        assert!(state.get(runner).unwrap().on_ground);
+        assert_eq!((pos.get(runner).unwrap().x, pos.get(runner).unwrap().y), (164.0, 96.0));
+
+        let jumper_physics = physics.get(jumper).unwrap();
+        assert_eq!(jumper_physics.velocity.y, 0.0);
+        assert_eq!(jumper_physics.contacts, Contacts { top: true, ..Contacts::default() });
+        assert!(!state.get(jumper).unwrap().on_ground);
+    }
 }
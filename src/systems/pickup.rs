@@ -0,0 +1,59 @@
+use ggez::graphics::Rect;
+use specs::{Entities, Fetch, System, ReadStorage, WriteStorage, Join};
+
+use crate::{
+    components::*,
+    game::PLAYER_COLLISION_GROUP,
+    input::PlayerInputState,
+    item::ItemFactory,
+    map::{TILE_WIDTH, TILE_HEIGHT},
+    spatial::SpatialQuery,
+};
+
+// Hands the player whatever weapon is lying on a tile it touches, replacing
+// the one it carried
+pub struct PickupSys;
+impl<'a> System<'a> for PickupSys {
+    type SystemData = (
+        Entities<'a>,
+        Fetch<'a, PlayerInputState>,
+        Fetch<'a, SpatialQuery>,
+        Fetch<'a, ItemFactory>,
+        ReadStorage<'a, PositionComp>,
+        ReadStorage<'a, PickupComp>,
+        WriteStorage<'a, EquipmentComp>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("<- PickupSys");
+        let (entities, player_input, spatial, items, position, pickups, mut equipment) = data;
+
+        for (e, p, pickup) in (&*entities, &position, &pickups).join() {
+            let tile = Rect::new(p.x, p.y, TILE_WIDTH as f32, TILE_HEIGHT as f32);
+            let touched = spatial.within_rect(tile, &[PLAYER_COLLISION_GROUP]).iter()
+                .any(|hit| hit.ent == player_input.ent);
+            if !touched {
+                continue;
+            }
+
+            let weapon = match items.find_weapon(&pickup.item) {
+                Some(weapon) => weapon,
+                None => {
+                    warn!("Pickup {:?} isn't in weapons.ron, leaving it", pickup.item);
+                    continue;
+                },
+            };
+            match equipment.get_mut(player_input.ent) {
+                Some(eq) => eq.weapon = weapon,
+                None => {
+                    equipment.insert(player_input.ent, EquipmentComp::new(weapon, vec![]));
+                },
+            }
+            info!("Picked up {:?}", pickup.item);
+            if let Err(err) = entities.delete(e) {
+                warn!("Pickup was already deleted, {:?}", err);
+            }
+        }
+        info!("-> PickupSys");
+    }
+}
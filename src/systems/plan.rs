@@ -1,4 +1,4 @@
-use specs::{Fetch, System, WriteStorage};
+use specs::{FetchMut, System, WriteStorage};
 
 use crate::{
     components::*,
@@ -13,15 +13,16 @@ impl PlanSys {
 
 impl<'a> System<'a> for PlanSys {
     type SystemData = (
-        Fetch<'a, PlayerInputState>,
+        FetchMut<'a, PlayerInputState>,
         WriteStorage<'a, StateComp>,
     );
 
-    fn run(&mut self, (player_input, mut act): Self::SystemData) {
+    fn run(&mut self, (mut player_input, mut act): Self::SystemData) {
         info!("<- PlanSys");
 
         let act_comp = act.get_mut(player_input.ent).unwrap();
         act_comp.move_action = player_input.input;
+        act_comp.attack_action = player_input.attack.take();
 
         info!("-> PlanSys");
     }
@@ -1,17 +1,23 @@
-use specs::{System, WriteStorage};
+use specs::{System, WriteStorage, Join};
 
 use crate::{
     components::PositionComp,
 };
 
+// Remembers where everything is before a simulation tick moves it, so drawing
+// can interpolate between ticks. Run before dispatching each tick.
 pub struct PositionSys;
 impl<'a> System<'a> for PositionSys {
     type SystemData = (
         WriteStorage<'a, PositionComp>,
     );
 
-    fn run(&mut self, _data: Self::SystemData) {
+    fn run(&mut self, (mut position,): Self::SystemData) {
         info!("<- PositionSys");
+        for p in (&mut position).join() {
+            p.prev_x = p.x;
+            p.prev_y = p.y;
+        }
         info!("-> PositionSys");
     }
 }
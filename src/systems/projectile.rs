@@ -0,0 +1,211 @@
+use ncollide2d::{
+    shape::{Cuboid, ShapeHandle},
+    world::{CollisionGroups, GeometricQueryType},
+};
+use nalgebra::Isometry2;
+use specs::{Entities, Fetch, FetchMut, System, ReadStorage, WriteStorage, Join};
+
+use crate::{
+    CollisionWorld, Point2, Vector2,
+    combat::{Hit, HitEvents},
+    components::*,
+    game::{PLAYER_COLLISION_GROUP, PROJECTILE_COLLISION_GROUP},
+    map::{SkirmMap, MapPoint, TerrainEdit, TerrainEdits, TILE_WIDTH, TILE_HEIGHT},
+    resources::DeltaTime,
+    spatial::SpatialQuery,
+};
+
+// Width and height in pixels of a shot and its sprite
+const PROJECTILE_SIZE: f32 = 8.0;
+
+// Pixels between the points checked for walls along a shot's path, small
+// enough that it can't skip over a tile
+const WALL_CHECK_STEP: f32 = 8.0;
+
+// Turns attack actions into shots from the attacker's weapon, fired from the
+// middle of its sprite towards the middle of the target tile
+pub struct FireSys;
+impl<'a> System<'a> for FireSys {
+    type SystemData = (
+        Entities<'a>,
+        FetchMut<'a, CollisionWorld>,
+        WriteStorage<'a, StateComp>,
+        ReadStorage<'a, EquipmentComp>,
+        WriteStorage<'a, PositionComp>,
+        WriteStorage<'a, SpriteComp>,
+        WriteStorage<'a, PhysicsComp>,
+        WriteStorage<'a, CollideComp>,
+        WriteStorage<'a, ProjectileComp>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("<- FireSys");
+        let (entities, mut collide_world, mut state, equipment, mut position, mut sprite, mut physics, mut collide, mut projectile) = data;
+
+        let mut shots = Vec::new();
+        for (e, s, eq, p) in (&*entities, &mut state, &equipment, &position).join() {
+            // Reeling from a hit drops the shot rather than saving it for later
+            let attack = s.attack_action.take();
+            if s.is_stunned() {
+                continue;
+            }
+            if let Some(target) = attack {
+                let origin = Point2::new(p.x + TILE_WIDTH as f32 / 2.0, p.y + TILE_HEIGHT as f32 / 2.0);
+                let (x, y) = target.as_float_coord_tuple();
+                let aim = Point2::new(x + TILE_WIDTH as f32 / 2.0, y + TILE_HEIGHT as f32 / 2.0);
+                shots.push((e, origin, aim, eq.weapon.clone()));
+            }
+        }
+
+        for (owner, origin, aim, weapon) in shots {
+            let direction = aim - origin;
+            if direction.norm() < std::f32::EPSILON {
+                continue;
+            }
+
+            let ent = entities.create();
+            let half = PROJECTILE_SIZE / 2.0;
+            let range = weapon.range as f32 * TILE_WIDTH as f32;
+            position.insert(ent, PositionComp::new(origin.x - half, origin.y - half));
+            sprite.insert(ent, SpriteComp::new(String::from("bullet")));
+            physics.insert(ent, PhysicsComp::new(PhysicsType::Moveable, direction.normalize() * weapon.projectile_speed));
+            projectile.insert(ent, ProjectileComp::new(owner, weapon.damage, weapon.projectile_gravity, range, weapon.blast_radius));
+
+            let mut group = CollisionGroups::new();
+            group.set_membership(&[PROJECTILE_COLLISION_GROUP]);
+            group.set_blacklist(&[PROJECTILE_COLLISION_GROUP]);
+            let handle = collide_world.add(
+                Isometry2::new(origin.coords, nalgebra::zero()),
+                ShapeHandle::new(Cuboid::new(Vector2::new(half, half))),
+                group,
+                GeometricQueryType::Contacts(0.0, 0.0),
+                ent,
+            );
+            collide.insert(ent, CollideComp { handle });
+        }
+        info!("-> FireSys");
+    }
+}
+
+// Flies shots along, spending them on the first wall or skirmer in their way
+// or once they've gone their range. Hitting a skirmer raises a hit with the
+// weapon's damage, and explosive shots blow up whatever they hit, hurting
+// everyone within the blast too.
+pub struct ProjectileSys;
+impl<'a> System<'a> for ProjectileSys {
+    type SystemData = (
+        Entities<'a>,
+        Fetch<'a, DeltaTime>,
+        Fetch<'a, SkirmMap>,
+        Fetch<'a, SpatialQuery>,
+        FetchMut<'a, HitEvents>,
+        FetchMut<'a, TerrainEdits>,
+        FetchMut<'a, CollisionWorld>,
+        WriteStorage<'a, PositionComp>,
+        WriteStorage<'a, PhysicsComp>,
+        WriteStorage<'a, ProjectileComp>,
+        ReadStorage<'a, CollideComp>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("<- ProjectileSys");
+        let (entities, time, map, spatial, mut hits, mut edits, mut collide_world, mut position, mut physics, mut projectile, collide) = data;
+        let dt = time.as_dt();
+        let half = PROJECTILE_SIZE / 2.0;
+
+        let mut spent = Vec::new();
+        let mut blasts = Vec::new();
+        for (e, pos, p, proj, c) in (&*entities, &mut position, &mut physics, &mut projectile, &collide).join() {
+            p.velocity.y += proj.gravity * dt;
+            let step = p.velocity * dt;
+            let length = step.norm().min(proj.range_left);
+            if length < std::f32::EPSILON {
+                spent.push((e, c.handle));
+                continue;
+            }
+
+            let center = Point2::new(pos.x + half, pos.y + half);
+            let dir = step.normalize();
+            let wall = distance_to_wall(&map, &center, &dir, length);
+            let target = spatial.cast_ray(center, dir, length, &[PLAYER_COLLISION_GROUP]).into_iter()
+                .find(|hit| hit.ent != proj.owner)
+                .filter(|hit| wall.map_or(true, |distance| hit.distance <= distance));
+
+            let impact = target.map(|target| target.distance).or(wall);
+            if let Some(target) = target {
+                hits.hits.push(Hit {
+                    attacker: Some(proj.owner),
+                    target: target.ent,
+                    damage: proj.damage,
+                    direction: p.velocity,
+                });
+            }
+
+            if let Some(distance) = impact {
+                if proj.blast_radius > 0 {
+                    let blast_center = center + dir * distance;
+                    edits.edits.push(TerrainEdit::Explosion(tile_at(&blast_center), proj.blast_radius));
+                    let direct = target.map(|target| target.ent);
+                    blasts.push((proj.owner, blast_center, proj.blast_radius, proj.damage, direct, p.velocity));
+                }
+                spent.push((e, c.handle));
+            } else {
+                let moved = center + dir * length;
+                pos.x = moved.x - half;
+                pos.y = moved.y - half;
+                collide_world.set_position(c.handle, Isometry2::new(moved.coords, nalgebra::zero()));
+
+                proj.range_left -= length;
+                if proj.range_left <= 0.0 {
+                    spent.push((e, c.handle));
+                }
+            }
+        }
+
+        // Blasts hurt everyone else caught in them, pushing them away from the middle
+        for (owner, blast_center, blast_radius, damage, direct, velocity) in blasts {
+            let radius = blast_radius as f32 * TILE_WIDTH as f32;
+            for caught in spatial.within_radius(blast_center, radius, &[PLAYER_COLLISION_GROUP]) {
+                if Some(caught.ent) == direct {
+                    continue;
+                }
+                let away = position.get(caught.ent)
+                    .map(|p| Point2::new(p.x + TILE_WIDTH as f32 / 2.0, p.y + TILE_HEIGHT as f32 / 2.0) - blast_center)
+                    .filter(|away| away.norm() > std::f32::EPSILON)
+                    .unwrap_or(velocity);
+                hits.hits.push(Hit {
+                    attacker: Some(owner),
+                    target: caught.ent,
+                    damage,
+                    direction: away,
+                });
+            }
+        }
+
+        for (e, handle) in spent {
+            collide_world.remove(&[handle]);
+            if let Err(err) = entities.delete(e) {
+                warn!("Projectile was already deleted, {:?}", err);
+            }
+        }
+        info!("-> ProjectileSys");
+    }
+}
+
+/// How far along `dir` from `origin`, within `length` pixels, the first solid
+/// tile is. Anything off the map counts as solid.
+fn distance_to_wall(map: &SkirmMap, origin: &Point2, dir: &Vector2, length: f32) -> Option<f32> {
+    let checks = (length / WALL_CHECK_STEP).ceil() as i32;
+    (0..=checks)
+        .map(|i| (i as f32 * WALL_CHECK_STEP).min(length))
+        .find(|distance| map.is_opaque(&tile_at(&(origin + dir * *distance))))
+}
+
+/// The tile a pixel position is in.
+fn tile_at(point: &Point2) -> MapPoint {
+    MapPoint::new(
+        (point.x / TILE_WIDTH as f32).floor() as i32,
+        (point.y / TILE_HEIGHT as f32).floor() as i32,
+        0,
+    )
+}
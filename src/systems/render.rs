@@ -1,12 +1,13 @@
-use specs::{Fetch, System, WriteStorage, ReadStorage, Join};
-use ggez::{graphics, graphics::Point2, Context};
+use specs::{Entities, Fetch, System, WriteStorage, ReadStorage, Join};
+use ggez::{graphics, graphics::{Color, Point2}, Context};
 
 use crate::{
     asset_storage::AssetStorage,
     camera::Camera,
     components::*,
-    map::SkirmMap,
-    resources::DeltaTime,
+    input::PlayerInputState,
+    map::{SkirmMap, MapPoint, TILE_WIDTH, TILE_HEIGHT},
+    resources::{DeltaTime, Interpolation},
 };
 
 pub struct AnimSys;
@@ -54,12 +55,12 @@ impl<'b, 'c> RenderSys<'b, 'c> {
         Self { ctx, camera }
     }
 
-    fn draw_image(&mut self, id: String, pos: (f32, f32), assets: &AssetStorage) {
+    fn draw_image(&mut self, id: String, pos: (f32, f32), color: Color, assets: &AssetStorage) {
         match assets.images.get(&id) {
             Some(image) => {
                 let cam = self.camera.get_world_center();
                 let point = Point2::new(pos.0 - cam.x, pos.1 - cam.y);
-                graphics::set_color(self.ctx, WHITE).unwrap();
+                graphics::set_color(self.ctx, color).unwrap();
                 graphics::draw(self.ctx, image, point, 0.0).unwrap();
             },
             None => (),
@@ -69,13 +70,18 @@ impl<'b, 'c> RenderSys<'b, 'c> {
 
 impl<'a, 'b, 'c> System<'a> for RenderSys<'b, 'c> {
     type SystemData = (
+        Entities<'a>,
         Fetch<'a, AssetStorage>,
         Fetch<'a, SkirmMap>,
+        Fetch<'a, PlayerInputState>,
+        Fetch<'a, Interpolation>,
         ReadStorage<'a, SpriteComp>,
         ReadStorage<'a, PositionComp>,
+        ReadStorage<'a, VisionComp>,
     );
 
-    fn run(&mut self, (assets, map, sprite_comp, position_comp): Self::SystemData) {
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, assets, map, player_input, interpolation, sprite_comp, position_comp, vision_comp) = data;
         info!("<- RenderSys");
         // Draw map
         // for (point, tile) in &map.map {
@@ -86,9 +92,32 @@ impl<'a, 'b, 'c> System<'a> for RenderSys<'b, 'c> {
         //     }
         // }
 
-        // Draw entities
-        for (s, p) in (&sprite_comp, &position_comp).join() {
-            self.draw_image(s.id.to_string(), (p.x, p.y), &assets);
+        // Draw entities, hiding what the player hasn't explored and dimming
+        // the tiles they remember but can't currently see
+        let vision = vision_comp.get(player_input.ent);
+        for (e, s, p) in (&*entities, &sprite_comp, &position_comp).join() {
+            let (x, y) = p.interpolated(interpolation.alpha);
+            let point = MapPoint::from_pixel_coord(x as i32 + TILE_WIDTH / 2, y as i32 + TILE_HEIGHT / 2, 0);
+
+            // Tiles of the other z-levels sit at the same pixels as the current one's
+            let tile_level = map.tile_entity_level(e, point.x, point.y);
+            if tile_level.map_or(false, |z| z != map.current_z) {
+                continue;
+            }
+
+            let color = match vision {
+                Some(v) => {
+                    if v.visible.contains(&point) {
+                        WHITE
+                    } else if v.explored.contains(&point) && tile_level.is_some() {
+                        DIM
+                    } else {
+                        continue;
+                    }
+                },
+                None => WHITE,
+            };
+            self.draw_image(s.id.to_string(), (x, y), color, &assets);
         }
         info!("-> RenderSys");
     }
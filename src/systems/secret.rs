@@ -0,0 +1,73 @@
+use specs::{Fetch, FetchMut, System, ReadStorage, Join};
+
+use std::collections::HashSet;
+
+use crate::{
+    components::*,
+    map::{SkirmMap, MapPoint, TerrainEdit, TerrainEdits, TILE_WIDTH, TILE_HEIGHT, tile_distance},
+    resources::DeltaTime,
+};
+
+// How close and for how long, in tiles and seconds, a secret wall has to be
+// watched before it gives itself away
+const LINGER_RANGE: u16 = 2;
+const LINGER_TIME: f32 = 3.0;
+
+// Finds secret walls that have been smashed or looked at long enough, and
+// queues them up to be revealed
+pub struct SecretSys;
+impl<'a> System<'a> for SecretSys {
+    type SystemData = (
+        Fetch<'a, DeltaTime>,
+        FetchMut<'a, SkirmMap>,
+        FetchMut<'a, TerrainEdits>,
+        ReadStorage<'a, PositionComp>,
+        ReadStorage<'a, StateComp>,
+        ReadStorage<'a, StatsComp>,
+        ReadStorage<'a, VisionComp>,
+    );
+
+    fn run(&mut self, (time, mut map, mut edits, position, state, stats, vision): Self::SystemData) {
+        info!("<- SecretSys");
+        let dt = time.as_dt();
+
+        // Strong enough entities smash through by walking into them, as
+        // strong as the wall's legend entry asks for
+        for (p, s, st) in (&position, &state, &stats).join() {
+            if !s.is_moving_horizontal() {
+                continue;
+            }
+
+            let dir = if s.move_action.right.state { 1 } else { -1 };
+            let side = tile_at(p).offset(dir, 0);
+            if map.smash_strength(&side).map_or(false, |needed| st.strength >= needed) {
+                edits.edits.push(TerrainEdit::Reveal(side));
+            }
+        }
+
+        // Secret walls watched from up close give themselves away
+        let mut watched = HashSet::new();
+        for (p, v) in (&position, &vision).join() {
+            let eye = tile_at(p);
+            for point in &v.visible {
+                if map.is_secret(point) && tile_distance(&eye, *point) <= LINGER_RANGE {
+                    watched.insert(*point);
+                }
+            }
+        }
+
+        map.secret_linger.retain(|point, _| watched.contains(point));
+        for point in watched {
+            let linger = map.secret_linger.entry(point).or_insert(0.0);
+            *linger += dt;
+            if *linger >= LINGER_TIME {
+                edits.edits.push(TerrainEdit::Reveal(point));
+            }
+        }
+        info!("-> SecretSys");
+    }
+}
+
+fn tile_at(p: &PositionComp) -> MapPoint {
+    MapPoint::from_pixel_coord(p.x as i32 + TILE_WIDTH / 2, p.y as i32 + TILE_HEIGHT / 2, 0)
+}
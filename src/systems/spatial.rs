@@ -0,0 +1,36 @@
+use specs::{Fetch, FetchMut, System, ReadStorage, Join};
+
+use crate::{
+    CollisionWorld,
+    Point2,
+    components::*,
+    spatial::SpatialQuery,
+};
+
+// Refills the spatial hash from the collision world once everything has moved
+pub struct SpatialSys;
+impl<'a> System<'a> for SpatialSys {
+    type SystemData = (
+        FetchMut<'a, SpatialQuery>,
+        Fetch<'a, CollisionWorld>,
+        ReadStorage<'a, CollideComp>,
+    );
+
+    fn run(&mut self, (mut spatial, collide_world, collide): Self::SystemData) {
+        info!("<- SpatialSys");
+        spatial.clear();
+        for c in (&collide).join() {
+            if let Some(obj) = collide_world.collision_object(c.handle) {
+                let aabb = obj.shape().aabb(obj.position());
+                let (mins, maxs) = (aabb.mins(), aabb.maxs());
+                spatial.insert(
+                    *obj.data(),
+                    Point2::new(mins.x, mins.y),
+                    Point2::new(maxs.x, maxs.y),
+                    obj.collision_groups().clone(),
+                );
+            }
+        }
+        info!("-> SpatialSys");
+    }
+}
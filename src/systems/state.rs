@@ -1,13 +1,15 @@
-use specs::{Entity, Fetch, System, WriteStorage, Join};
+use specs::{Entities, Entity, Fetch, System, WriteStorage, Join};
 
 use crate::{
-    Vector2,
     components::*,
     resources::DeltaTime,
     map::SkirmMap,
     item::Weapon,
 };
 
+// Pixels per second, for skirmers that don't give their own
+pub const MOVE_SPEED: f32 = 100.0;
+
 // Performs entities' `current_action`s
 pub struct StateSys;
 impl StateSys {
@@ -23,38 +25,44 @@ impl StateSys {
 
 impl<'a> System<'a> for StateSys {
     type SystemData = (
+        Entities<'a>,
         Fetch<'a, DeltaTime>,
         WriteStorage<'a, StatsComp>,
         WriteStorage<'a, StateComp>,
         WriteStorage<'a, PositionComp>,
         WriteStorage<'a, AnimComp>,
         WriteStorage<'a, PhysicsComp>,
+        WriteStorage<'a, JumpComp>,
         Fetch<'a, SkirmMap>,
     );
 
-    fn run(&mut self, (time, mut _stats, mut action, mut pos, mut anim, mut physics, _map): Self::SystemData) {
+    fn run(&mut self, (entities, time, stats, mut action, mut pos, mut anim, mut physics, mut jump, _map): Self::SystemData) {
         info!("<- StateSys");
         let dt = time.as_dt();
 
-        for (a, _p, n, y) in (&mut action, &mut pos, &mut anim, &mut physics).join() {
+        for (e, a, _p, n, y) in (&*entities, &mut action, &mut pos, &mut anim, &mut physics).join() {
             if a.move_action.is_any_unhandled() {
             }
 
+            // Reeling from a hit, the knockback carries it instead
+            if a.is_stunned() {
+                a.hit_stun = (a.hit_stun - dt).max(0.0);
+                continue;
+            }
+
+            // Only walking is handled here, up jumps and down drops through
+            // platforms or climbs
+            let run_speed = stats.get(e).map_or(MOVE_SPEED, |s| s.run_speed);
+            y.velocity.x = if a.move_action.left.state {
+                -run_speed
+            } else if a.move_action.right.state {
+                run_speed
+            } else {
+                0.0
+            };
+
             if a.is_moving() {
                 info!("Ent moving {:?}", a.move_action);
-                let speed = 100.0 * dt;
-                if a.move_action.up.state {
-                    y.velocity = Vector2::new(0.0, -speed);
-                }
-                if a.move_action.down.state {
-                    y.velocity = Vector2::new(0.0, speed);
-                }
-                if a.move_action.left.state {
-                    y.velocity = Vector2::new(-speed, 0.0);
-                }
-                if a.move_action.right.state {
-                    y.velocity = Vector2::new(speed, 0.0);
-                }
             } else {
                 if a.move_action.dirty {
                     n.change_id(String::from("idle"), true);
@@ -66,8 +74,46 @@ impl<'a> System<'a> for StateSys {
                 info!("Ent attacking {:?}", point);
             }
         }
+
+        for (a, y, j) in (&mut action, &mut physics, &mut jump).join() {
+            update_jump(a, y, j, dt);
+        }
         info!("-> StateSys");
     }
 }
 
+/// Starts a jump on a fresh press of up while on the ground, or shortly after
+/// leaving it, and keeps a press from just before landing until it lands.
+/// Letting go on the way up cuts the jump short.
+fn update_jump(a: &mut StateComp, y: &mut PhysicsComp, j: &mut JumpComp, dt: f32) {
+    let held = a.move_action.up.state;
+    let pressed = held && !j.held;
+    j.held = held;
+
+    // Up on a ladder climbs instead, and nothing jumps while stunned
+    if a.climbing || a.is_stunned() {
+        j.coyote = 0.0;
+        j.buffer = 0.0;
+        j.rising = false;
+        return;
+    }
+
+    j.coyote = if a.is_on_ground() { j.params.coyote_time } else { (j.coyote - dt).max(0.0) };
+    j.buffer = if pressed { j.params.buffer_time } else { (j.buffer - dt).max(0.0) };
+
+    if j.buffer > 0.0 && j.coyote > 0.0 {
+        y.velocity.y = -j.params.speed;
+        a.on_ground = false;
+        a.on_surface = false;
+        j.coyote = 0.0;
+        j.buffer = 0.0;
+        j.rising = true;
+    } else if j.rising && (!held || y.velocity.y >= 0.0) {
+        if y.velocity.y < 0.0 {
+            y.velocity.y *= j.params.release_cut;
+        }
+        j.rising = false;
+    }
+}
+
 
@@ -0,0 +1,49 @@
+use specs::{Entities, FetchMut, System, WriteStorage};
+
+use crate::{
+    CollisionWorld,
+    components::*,
+    map::{SkirmMap, TerrainAccess, TerrainEdit, TerrainEdits, TerrainEvents},
+};
+
+// Applies the terrain edits queued up by other systems this tick
+pub struct TerrainSys;
+impl<'a> System<'a> for TerrainSys {
+    type SystemData = (
+        Entities<'a>,
+        FetchMut<'a, SkirmMap>,
+        FetchMut<'a, TerrainEdits>,
+        FetchMut<'a, TerrainEvents>,
+        FetchMut<'a, CollisionWorld>,
+        WriteStorage<'a, PositionComp>,
+        WriteStorage<'a, SpriteComp>,
+        WriteStorage<'a, CollideComp>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("<- TerrainSys");
+        let (entities, mut map, mut edits, mut events, mut collide_world, mut positions, mut sprites, mut collide) = data;
+
+        let mut access = TerrainAccess {
+            entities: &entities,
+            positions: &mut positions,
+            sprites: &mut sprites,
+            collide: &mut collide,
+            collide_world: &mut *collide_world,
+            events: &mut *events,
+        };
+
+        for edit in edits.edits.drain(..) {
+            let result = match edit {
+                TerrainEdit::Dig(point) => map.dig(&point, &mut access),
+                TerrainEdit::Place(point, glyph) => map.place(&point, glyph, &mut access),
+                TerrainEdit::Reveal(point) => map.reveal(&point, &mut access),
+                TerrainEdit::Explosion(center, radius) => map.explode(&center, radius, &mut access),
+            };
+            if let Err(e) = result {
+                warn!("Terrain edit {:?} failed, {}", edit, e);
+            }
+        }
+        info!("-> TerrainSys");
+    }
+}
@@ -0,0 +1,26 @@
+use specs::{Fetch, System, ReadStorage, WriteStorage, Join};
+
+use crate::{
+    components::*,
+    map::{SkirmMap, MapPoint, TILE_WIDTH, TILE_HEIGHT},
+};
+
+// Updates what each seeing entity can see now and has ever seen
+pub struct VisionSys;
+impl<'a> System<'a> for VisionSys {
+    type SystemData = (
+        Fetch<'a, SkirmMap>,
+        ReadStorage<'a, PositionComp>,
+        WriteStorage<'a, VisionComp>,
+    );
+
+    fn run(&mut self, (map, position, mut vision): Self::SystemData) {
+        info!("<- VisionSys");
+        for (p, v) in (&position, &mut vision).join() {
+            let eye = MapPoint::from_pixel_coord(p.x as i32 + TILE_WIDTH / 2, p.y as i32 + TILE_HEIGHT / 2, 0);
+            v.visible = map.field_of_view(&eye, v.radius);
+            v.explored.extend(v.visible.iter().cloned());
+        }
+        info!("-> VisionSys");
+    }
+}